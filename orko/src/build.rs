@@ -1,4 +1,5 @@
 use sass_rs;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std;
 use std::fs;
@@ -7,7 +8,14 @@ use std::io::{Write, Read};
 use filetime::{FileTime, set_file_times};
 
 use templar;
-use templar::{TemplateContext, Node};
+use markdown;
+use pagination;
+use records;
+use page::{self, Page};
+use site::Site;
+use templar::{TemplateContext, Node, Value};
+use templar::output::{OutputMode, Sink};
+use templar::parse::ParseOptions;
 
 #[derive(Debug)]
 pub struct ProcessedFile {
@@ -16,10 +24,12 @@ pub struct ProcessedFile {
     pub result: Result<PathBuf, BuildErrorReason>,
 }
 
-struct TemplarDirectiveHandler {
+struct TemplarDirectiveHandler<'a> {
+    site: &'a Site,
     pub current_directory: PathBuf,
     pub destination_directory: PathBuf,
     include_paths: Vec<PathBuf>,
+    file_stack: Vec<PathBuf>, // templates currently being rendered, outermost first
 }
 
 #[derive(Debug)]
@@ -36,33 +46,42 @@ pub enum BuildAction {
 pub enum BuildErrorReason {
     IO(io::Error),
     Sass(String),
-    TemplarParse(templar::parse::ParseError),
+    TemplarParse(Vec<templar::parse::ParseError>),
     TemplarWrite(templar::output::WriteError<DirectiveError>),
     UTF8Error(std::string::FromUtf8Error),
+    FrontMatter(String),
 }
 
 #[derive(Debug)]
 pub struct DirectiveError {
     pub directive: String,
-    pub reason: String
+    pub reason: String,
+    pub stack: Vec<StackFrame>, // innermost first
 }
 
-/// Returns valid site directories give a path.
-pub fn build_path(path:&Path) -> bool {
-    let path = path.iter().last().expect("a last component in a path");
-    if let Some(path_str) = path.to_str() {
-        !(path_str.starts_with(".") || path_str.starts_with("_"))
-    } else {
-        false
+impl From<templar::directives::DirectiveError> for DirectiveError {
+    fn from(err: templar::directives::DirectiveError) -> Self {
+        DirectiveError {
+            directive: err.directive,
+            reason: err.reason,
+            stack: Vec::new(),
+        }
     }
 }
 
-pub fn build(source: &Path, destination: &Path) -> io::Result<Vec<ProcessedFile>> {
+#[derive(Debug, Clone)]
+pub struct StackFrame {
+    pub file: PathBuf,
+    pub directive: String, // empty when the file itself failed to parse
+    pub line: Option<usize>, // 1-based, of the directive or of the line that failed to parse
+}
+
+pub fn build(site:&Site, source: &Path, destination: &Path) -> io::Result<Vec<ProcessedFile>> {
     fs::create_dir_all(destination)?;
     let paths = read_directory_paths(source)?;
 
     Ok(paths.into_iter().flat_map(|path| {
-        if build_path(&path) {
+        if !site.config.ignores(&path) {
             // current target file/dir
             let new_dest = {
                 let last = path.iter().last().expect("a last path component");
@@ -70,7 +89,7 @@ pub fn build(source: &Path, destination: &Path) -> io::Result<Vec<ProcessedFile>
             };
 
             if path.is_dir() {
-                match build(&path, new_dest.as_path()) {
+                match build(site, &path, new_dest.as_path()) {
                     Ok(results) => results,
                     Err(io) => {
                         vec![ProcessedFile {
@@ -81,43 +100,47 @@ pub fn build(source: &Path, destination: &Path) -> io::Result<Vec<ProcessedFile>
                     }
                 }
             } else {
-                // file to process
-                let (action, result) : (BuildAction, Result<PathBuf, BuildErrorReason>) = match path.extension().and_then(|oss| oss.to_str()) {
+                // file to process, pages can be written to several outputs
+                let processed : Vec<(BuildAction, Result<PathBuf, BuildErrorReason>)> = match path.extension().and_then(|oss| oss.to_str()) {
                     Some("templar") => {
-                        (
-                            BuildAction::Compile { extension: "templar".into(), destination: new_dest.clone() },
-                            compile_templar(source, destination, &path, &new_dest)
-                        )
+                        compile_templar(site, source, destination, &path, &new_dest).into_iter().map(|(output, result)| {
+                            (BuildAction::Compile { extension: "templar".into(), destination: output }, result)
+                        }).collect()
                     },
-                    Some("sass") => {(
+                    Some("md") | Some("markdown") => {
+                        compile_markdown(site, source, destination, &path, &new_dest).into_iter().map(|(output, result)| {
+                            (BuildAction::Compile { extension: "md".into(), destination: output }, result)
+                        }).collect()
+                    },
+                    Some("sass") => vec![(
                         BuildAction::Compile { extension: "sass".into(), destination: new_dest.clone() },
-                        compile_sass(source, &path, &new_dest)
-                    )},
+                        compile_sass(site, source, &path, &new_dest)
+                    )],
                     _ => {
                         if same_attributes(&path, &new_dest) {
                             // (BuildAction::Skip, Ok(source.to_path_buf()))
-                            ( BuildAction::Copy(new_dest.clone()),
+                            vec![( BuildAction::Copy(new_dest.clone()),
                             match copy_maintaining_modified_time(&path, &new_dest) {
                                 Ok(_) => Ok(source.to_path_buf()),
                                 Err(io) => Err(BuildErrorReason::IO(io)),
-                            } )
+                            } )]
                         } else {
-                            (
+                            vec![(
                                 BuildAction::Copy(new_dest.clone()),
                                 match copy_maintaining_modified_time(&path, &new_dest) {
                                     Ok(_) => Ok(source.to_path_buf()),
                                     Err(io) => Err(BuildErrorReason::IO(io)),
                                 }
-                            )
+                            )]
                         }
                     }
                 };
 
-                vec![ProcessedFile {
-                    source: path,
+                processed.into_iter().map(|(action, result)| ProcessedFile {
+                    source: path.clone(),
                     action: action,
                     result: result,
-                }]
+                }).collect()
             }
         } else {
             vec![ProcessedFile {
@@ -129,6 +152,46 @@ pub fn build(source: &Path, destination: &Path) -> io::Result<Vec<ProcessedFile>
     }).collect())
 }
 
+/// Builds a site's files, then the pages of its taxonomies.
+pub fn build_site(site:&Site, source: &Path, destination: &Path) -> io::Result<Vec<ProcessedFile>> {
+    let mut processed = build(site, source, destination)?;
+    processed.extend(build_taxonomies(site));
+    Ok(processed)
+}
+
+/// Renders a page for every term of every taxonomy, and the index of terms when the taxonomy has an index layout.
+pub fn build_taxonomies(site:&Site) -> Vec<ProcessedFile> {
+    let mut processed = Vec::new();
+    for (name, settings) in site.config.taxonomies.iter() {
+        let terms = site.taxonomies.get(name).map_or(&[][..], |t| t.as_slice());
+        let mut generated = Vec::new();
+        for term in terms {
+            let mut front_matter = BTreeMap::new();
+            front_matter.insert("title".to_string(), Value::String(term.name.clone()));
+            let mut vars = BTreeMap::new();
+            vars.insert("term".to_string(), term.value());
+            generated.push((&settings.layout, Page::generated(&site.config, &site.config.layout_path(&settings.layout), &term.url, front_matter), vars));
+        }
+        if let Some(ref index_layout) = settings.index_layout {
+            let mut front_matter = BTreeMap::new();
+            front_matter.insert("title".to_string(), Value::String(name.clone()));
+            let mut vars = BTreeMap::new();
+            vars.insert("terms".to_string(), Value::Array(terms.iter().map(|t| t.value()).collect()));
+            generated.push((index_layout, Page::generated(&site.config, &site.config.layout_path(index_layout), &settings.path, front_matter), vars));
+        }
+
+        for (layout, page, vars) in generated {
+            let result = render_page(site, &page, vars, Vec::new(), Some(layout), &site.directory, &site.config.output);
+            processed.push(ProcessedFile {
+                source: page.source,
+                action: BuildAction::Compile { extension: "templar".into(), destination: page.output },
+                result: result,
+            });
+        }
+    }
+    processed
+}
+
 pub fn copy_maintaining_modified_time(source:&Path, dest:&Path) -> io::Result<()> {
     fs::copy(source, dest).and_then(|_|
         fs::metadata(source)
@@ -155,35 +218,134 @@ pub fn same_attributes(a: &Path, b:&Path) -> bool {
     }
 }
 
-pub fn compile_templar(base_directory:&Path, target_base_directory:&Path, source:&Path, destination:&Path) -> Result<PathBuf, BuildErrorReason> {
+/// Renders a templar page, returning each output it was written to with its result. Drafts aren't written.
+pub fn compile_templar(site:&Site, base_directory:&Path, target_base_directory:&Path, source:&Path, destination:&Path) -> Vec<(PathBuf, Result<PathBuf, BuildErrorReason>)> {
+    let page = match read_page(site, source, destination) {
+        Ok(page) => page,
+        Err(err) => return vec![(destination.to_path_buf(), Err(err))],
+    };
+    if page.is_draft() && !site.config.drafts {
+        return Vec::new();
+    }
+
+    match parse_source(&page.body, page.mode) {
+        Ok(nodes) => render_pages(site, &page, nodes, page.layout(), base_directory, target_base_directory),
+        Err(err) => vec![(page.output.clone(), Err(err))],
+    }
+}
+
+/// Renders a Markdown page into the layout named in its front matter or configured for its directory.
+pub fn compile_markdown(site:&Site, base_directory:&Path, target_base_directory:&Path, source:&Path, destination:&Path) -> Vec<(PathBuf, Result<PathBuf, BuildErrorReason>)> {
+    let page = match read_page(site, source, destination) {
+        Ok(page) => page,
+        Err(err) => return vec![(destination.to_path_buf(), Err(err))],
+    };
+    if page.is_draft() && !site.config.drafts {
+        return Vec::new();
+    }
+
+    let html = markdown::render_markdown(&page.body, &site.highlighter);
+    let nodes = vec![Node::RawText(html.trim_end().to_string())];
+    let relative_path = source.strip_prefix(&site.directory).unwrap_or(source);
+    let layout = page.layout().or_else(|| site.config.markdown_layout(relative_path));
+    render_pages(site, &page, nodes, layout, base_directory, target_base_directory)
+}
+
+/// The page as the site read it, or read now for pages it doesn't list such as drafts and modules.
+fn read_page(site:&Site, source:&Path, destination:&Path) -> Result<Page, BuildErrorReason> {
+    match site.pages.iter().find(|p| p.source == source) {
+        Some(page) => Ok(page.clone()),
+        None => Page::read(&site.config, source, destination),
+    }
+}
+
+/// Renders a page once, once for every page of the list its `paginate` front matter names with that
+/// page of the list as `pagination`, or once for every record its `generate` front matter names
+/// with the record as `record`.
+fn render_pages(site:&Site, page:&Page, nodes: Vec<Node>, layout: Option<&str>, base_directory:&Path, target_base_directory:&Path) -> Vec<(PathBuf, Result<PathBuf, BuildErrorReason>)> {
+    let pages : Result<Vec<(Page, &str, Value)>, BuildErrorReason> = if let Some((expression, per_page)) = page.paginate() {
+        if let Some(other) = pagination::clashing_page(page, &site.pages) {
+            let reason = format!("paginate: {} already paginates into the same page/ directory, only one page in a directory can paginate", other.url);
+            return vec![(page.output.clone(), Err(BuildErrorReason::FrontMatter(reason)))];
+        }
+        evaluate_list(site, "paginate", expression).map(|items| {
+            pagination::paginate(page, items, per_page).into_iter().map(|(paged, pagination)| (paged, "pagination", pagination)).collect()
+        })
+    } else if let Some((expression, pattern)) = page.generate() {
+        evaluate_list(site, "generate", expression).and_then(|records| {
+            records::record_pages(&site.config, page, records, pattern).map_err(|e| BuildErrorReason::FrontMatter(format!("generate: {}", e)))
+        }).map(|pages| pages.into_iter().map(|(generated, record)| (generated, "record", record)).collect())
+    } else {
+        return vec![(page.output.clone(), render_page(site, page, BTreeMap::new(), nodes, layout, base_directory, target_base_directory))];
+    };
+
+    match pages {
+        Ok(pages) => pages.into_iter().map(|(page, name, value)| {
+            let mut vars = BTreeMap::new();
+            vars.insert(name.to_string(), value);
+            let result = render_page(site, &page, vars, nodes.clone(), layout, base_directory, target_base_directory);
+            (page.output, result)
+        }).collect(),
+        Err(err) => vec![(page.output.clone(), Err(err))],
+    }
+}
+
+/// The list a front matter expression names, such as `site.collections.posts`.
+fn evaluate_list(site:&Site, key:&str, expression:&str) -> Result<Vec<Value>, BuildErrorReason> {
+    match site.context.evaluate(expression).map(|v| v.into_owned()) {
+        Ok(Value::Array(items)) => Ok(items),
+        Ok(_) => Err(BuildErrorReason::FrontMatter(format!("{}: {} is not a list", key, expression))),
+        Err(err) => Err(BuildErrorReason::FrontMatter(format!("{}: {}", key, err))),
+    }
+}
+
+/// Writes a page's nodes to its output, as the nodes a layout yields when it has one. `vars` are
+/// set next to `page`.
+fn render_page(site:&Site, page:&Page, mut vars: BTreeMap<String, Value>, nodes: Vec<Node>, layout: Option<&str>, base_directory:&Path, target_base_directory:&Path) -> Result<PathBuf, BuildErrorReason> {
     let mut directive_handler = TemplarDirectiveHandler {
+        site,
         current_directory: base_directory.to_path_buf(),
         destination_directory: target_base_directory.to_path_buf(),
-        include_paths: vec![base_directory.to_path_buf()] };
-
-    let nodes = parse_template(source)?;
-    let out_path = destination.with_extension("html");
-    let mut file = fs::File::create(out_path)?;
+        include_paths: vec![base_directory.to_path_buf()],
+        file_stack: vec![page.source.clone()] };
 
-    let empty_context = TemplateContext::empty();
+    vars.insert("page".to_string(), page.value());
+    let context = site.context.with_vars(vars);
 
-    let _ = templar::output::write_out(nodes.as_slice(), &empty_context, &mut file, 0, 2, &mut directive_handler)?;
+    if let Some(parent) = page.output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = fs::File::create(&page.output)?;
+    let indent_size = site.config.indent_size();
+
+    let _ = match layout {
+        Some(layout) => {
+            let layout_path = site.config.layout_path(layout);
+            if !layout_path.exists() {
+                return Err(BuildErrorReason::FrontMatter(format!("there is no layout {} at {}", layout, layout_path.display())));
+            }
+            let layout_nodes = parse_template(&layout_path, page.mode)?;
+            directive_handler.file_stack.push(layout_path);
+            templar::output::write_out(layout_nodes.as_slice(), &context.yielding(nodes), &mut file, page.mode, 0, indent_size, &mut directive_handler)?
+        },
+        None => templar::output::write_out(nodes.as_slice(), &context, &mut file, page.mode, 0, indent_size, &mut directive_handler)?,
+    };
 
     file.sync_all()?;
 
     Ok(base_directory.to_path_buf())
 }
 
-pub fn compile_sass(base_directory:&Path, source:&Path, destination:&Path) -> Result<PathBuf, BuildErrorReason> {
-    let out = sass_rs::compile_file(source, sass_rs::Options::default()).map_err(BuildErrorReason::Sass)?;
+pub fn compile_sass(site:&Site, base_directory:&Path, source:&Path, destination:&Path) -> Result<PathBuf, BuildErrorReason> {
+    let out = sass_rs::compile_file(source, site.config.sass_options()).map_err(BuildErrorReason::Sass)?;
     write_to_path(&out, destination.with_extension("css").as_path())?;
     Ok(base_directory.to_path_buf())
 }
 
-impl templar::output::DirectiveHandler for TemplarDirectiveHandler {
+impl<'a> templar::output::DirectiveHandler for TemplarDirectiveHandler<'a> {
     type DirectiveError = DirectiveError;
 
-    fn handle<W>(&mut self, context:&TemplateContext, command: &str, children: &[Node], base_indent:usize, indent_size: usize, writer: &mut W) -> Result<(), DirectiveError> where W : Write {
+    fn handle(&mut self, context:&mut TemplateContext, command: &str, children: &[Node], base_indent:usize, indent_size: usize, sink: &mut Sink) -> Result<(), DirectiveError> {
         let parts : Vec<_> = command.split(" ").collect();
         match parts.first() {
             Some(&"module") => {
@@ -193,14 +355,11 @@ impl templar::output::DirectiveHandler for TemplarDirectiveHandler {
                     module_path.push(module);
 
                     // process the module directory
-                    let build_result = build(&module_path, self.destination_directory.as_path());
+                    let build_result = build(self.site, &module_path, self.destination_directory.as_path());
                     ::output::print_summary(&module_path, build_result);
 
-                    match build(&module_path, self.destination_directory.as_path()) {
-                        Err(e) => { return Err(DirectiveError {
-                            directive: command.to_string(),
-                            reason: format!("{}", e)
-                        })},
+                    match build(self.site, &module_path, self.destination_directory.as_path()) {
+                        Err(e) => { return Err(self.error(command, format!("{}", e))) },
                         _ => (),
                     };
 
@@ -208,10 +367,7 @@ impl templar::output::DirectiveHandler for TemplarDirectiveHandler {
                     self.include_paths.push(module_path);
                     Ok(())
                 } else {
-                    Err(DirectiveError {
-                        directive: command.to_string(),
-                        reason: format!("no module supplied in module command.").to_string(),
-                    })
+                    Err(self.error(command, format!("no module supplied in module command.")))
                 }
             }
             // Some(&"module_include") => {
@@ -256,13 +412,24 @@ impl templar::output::DirectiveHandler for TemplarDirectiveHandler {
             //         })
             //     }
             // },
-            Some(&"yield") => {
-                templar::output::write_out(context.nodes.as_slice(), &context, writer, base_indent, indent_size, self).map_err(|e| {
-                    DirectiveError {
-                        directive: command.to_string(),
-                        reason: format!("{:?}", e)
-                    }
-                })
+            Some(&"code") if sink.mode() != OutputMode::Text => {
+                let language = parts.get(1).cloned().unwrap_or("");
+                sink.write_indent(base_indent);
+                sink.write_str(&self.site.highlighter.highlight(&templar::directives::code_text(children), language));
+                if indent_size > 0 {
+                    sink.write_str("\n");
+                }
+                Ok(())
+            },
+            Some(&"yield") if parts.len() == 1 => {
+                // yielded nodes belong to the template that included this one
+                let yielding_file = if self.file_stack.len() > 1 { self.file_stack.pop() } else { None };
+                let nodes = context.nodes.clone();
+                let result = templar::output::render(nodes.as_slice(), context, sink, base_indent, indent_size, self);
+                if let Some(file) = yielding_file {
+                    self.file_stack.push(file);
+                }
+                result.map_err(|e| self.nested_error(command, e))
             },
             Some(&"include") => {
                 if let Some(second) = parts.get(1) {
@@ -278,34 +445,26 @@ impl templar::output::DirectiveHandler for TemplarDirectiveHandler {
                     }).nth(0) {
                         // found first file
 
-                        let include_nodes = parse_template(&file).map_err(|e| {
-                            DirectiveError {
-                                directive: command.to_string(),
-                                reason: format!("{:?}", e)
-                            }
-                        })?;
-
-                        let context = TemplateContext {
-                            nodes: children.iter().cloned().collect(),
+                        // the partial is on the stack while it is parsed, so its errors name it
+                        self.file_stack.push(file.clone());
+                        let include_nodes = match parse_template(&file, sink.mode()) {
+                            Ok(nodes) => nodes,
+                            Err(err) => {
+                                let frame = StackFrame { file, directive: String::new(), line: failing_line(&err) };
+                                self.file_stack.pop();
+                                let mut err = self.error(command, describe_build_error(&err));
+                                err.stack.insert(0, frame);
+                                return Err(err);
+                            },
                         };
 
+                        let mut context = context.yielding(children.to_vec());
+
                         // write the file!
-                        templar::output::write_out(include_nodes.as_slice(), &context, writer, base_indent, indent_size, self).map_err(|e| {
-                            match e {
-                                templar::output::WriteError::DirectiveError(err) => {
-                                    DirectiveError {
-                                        directive: command.to_string(),
-                                        reason: format!("{}", err.reason)
-                                    }
-                                },
-                                templar::output::WriteError::IO(err) => {
-                                    DirectiveError {
-                                        directive: command.to_string(),
-                                        reason: format!("{:?}", err)
-                                    }
-                                }
-                            }
-                        })
+                        let result = templar::output::render(include_nodes.as_slice(), &mut context, sink, base_indent, indent_size, self);
+                        self.file_stack.pop();
+
+                        result.map_err(|e| self.nested_error(command, e))
 
                     } else {
                         // no files found in include path
@@ -313,39 +472,96 @@ impl templar::output::DirectiveHandler for TemplarDirectiveHandler {
                         let paths: String = self.include_paths.iter().map(|p|
                             format!("\n    - {}", p.clone().into_os_string().into_string().unwrap())).collect();
 
-                        Err(DirectiveError {
-                            directive: command.to_string(),
-                            reason: format!("Could not find {} in the following paths:{}", second, paths),
-                        })
+                        Err(self.error(command, format!("Could not find {} in the following paths:{}", second, paths)))
                     }
 
                 } else {
-                    Err(DirectiveError {
-                        directive: command.to_string(),
-                        reason: "unrecognized".to_string(),
-                    })
+                    Err(self.error(command, "unrecognized".to_string()))
                 }
             },
-            Some(&"doctype") => {
-                writer.write_all(b"<!DOCTYPE html>\n").map_err(|_| DirectiveError {
-                    directive: command.to_string(),
-                    reason: "couldnt write doctype".to_string(),
-                })
-            },
             _ => {
-                Err(DirectiveError {
-                    directive: command.to_string(),
-                    reason: "unrecognized".to_string(),
-                })
+                match templar::directives::standard(self, context, command, children, base_indent, indent_size, sink) {
+                    Some(result) => result.map_err(|e| self.nested_error(command, e)),
+                    None => Err(self.error(command, "unrecognized".to_string())),
+                }
             }
         }
     }
 }
 
-pub fn parse_template(path:&Path) -> Result<Vec<templar::Node>, BuildErrorReason> {
+impl<'a> TemplarDirectiveHandler<'a> {
+    fn current_file(&self) -> PathBuf {
+        self.file_stack.last().cloned().unwrap_or_default()
+    }
+
+    fn frame(&self, command: &str) -> StackFrame {
+        let file = self.current_file();
+        let line = directive_line(&file, command);
+        StackFrame { file, directive: command.to_string(), line }
+    }
+
+    fn error(&self, command: &str, reason: String) -> DirectiveError {
+        DirectiveError {
+            directive: command.to_string(),
+            reason,
+            stack: vec![self.frame(command)],
+        }
+    }
+
+    /// Records this directive as the caller of an error raised by the nodes it rendered.
+    fn nested_error(&self, command: &str, err: templar::output::WriteError<DirectiveError>) -> DirectiveError {
+        match err {
+            templar::output::WriteError::DirectiveError(mut err) => {
+                err.stack.push(self.frame(command));
+                err
+            },
+            templar::output::WriteError::IO(err) => self.error(command, format!("{}", err)),
+            templar::output::WriteError::Expression(err) => self.error(command, format!("{}", err)),
+        }
+    }
+}
+
+/// The line of the first `=command` in a template. Nodes don't keep their lines, so a directive
+/// used twice is reported at the first use.
+fn directive_line(file:&Path, command:&str) -> Option<usize> {
+    let source = fs::read_to_string(file).ok()?;
+    source.lines()
+        .position(|line| line.trim_start().starts_with('=') && line.trim()[1..].trim() == command.trim())
+        .map(|idx| idx + 1)
+}
+
+/// The first line a template failed to parse at, 1-based.
+fn failing_line(err:&BuildErrorReason) -> Option<usize> {
+    match err {
+        &BuildErrorReason::TemplarParse(ref errors) => errors.first().map(|e| e.line_number + 1),
+        _ => None,
+    }
+}
+
+/// The reason a partial couldn't be read or parsed, for a directive error.
+fn describe_build_error(err:&BuildErrorReason) -> String {
+    match err {
+        &BuildErrorReason::TemplarParse(ref errors) => errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n"),
+        &BuildErrorReason::IO(ref err) => err.to_string(),
+        &BuildErrorReason::UTF8Error(ref err) => err.to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Parses a template, skipping any front matter.
+pub fn parse_template(path:&Path, mode: OutputMode) -> Result<Vec<templar::Node>, BuildErrorReason> {
     let template_str = read_path(&path)?;
-    let template_nodes = templar::parse::parse(&template_str)?;
-    Ok(template_nodes)
+    parse_source(&page::blank_front_matter(&template_str), mode)
+}
+
+fn parse_source(source:&str, mode: OutputMode) -> Result<Vec<templar::Node>, BuildErrorReason> {
+    let options = ParseOptions { recover: true, void_elements: mode == OutputMode::Html, ..ParseOptions::default() };
+    let parsed = templar::parse::parse_with(source, options);
+    if parsed.errors.is_empty() {
+        Ok(parsed.nodes)
+    } else {
+        Err(BuildErrorReason::TemplarParse(parsed.errors))
+    }
 }
 
 pub fn read_path(path:&Path) -> Result<String, BuildErrorReason> {
@@ -394,6 +610,6 @@ impl From<std::string::FromUtf8Error> for BuildErrorReason {
 
 impl From<templar::parse::ParseError> for BuildErrorReason {
     fn from(err: templar::parse::ParseError) -> Self {
-        BuildErrorReason::TemplarParse(err)
+        BuildErrorReason::TemplarParse(vec![err])
     }
 }
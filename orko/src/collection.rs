@@ -0,0 +1,143 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use templar::Value;
+use templar::filters::DateTime;
+
+use config::CollectionSettings;
+use highlight::Highlighter;
+use markdown;
+use page::Page;
+
+/// Where a Markdown excerpt ends when it isn't the first paragraph.
+const EXCERPT_SEPARATOR: &'static str = "<!-- more -->";
+
+/// Gathers the pages of a collection, filling in their `collection`, `slug`, `date` from a
+/// `YYYY-MM-DD-slug` file name, `excerpt`, `previous` and `next`. Returns them in the collection's order.
+/// Pages that paginate are the collection's index rather than part of it, and pages that generate
+/// others from records aren't written themselves.
+///
+/// Only Markdown pages get an `excerpt` derived from their body. Templar pages are only rendered
+/// once every collection is gathered, so a templar post needs `excerpt` in its front matter.
+pub fn collect(name:&str, settings:&CollectionSettings, pages:&mut [Page], highlighter:&Highlighter) -> Vec<Value> {
+    let mut members : Vec<usize> = (0..pages.len()).filter(|&idx| {
+        pages[idx].source.starts_with(&settings.directory) && pages[idx].paginate().is_none() && pages[idx].generate().is_none()
+    }).collect();
+
+    for &idx in members.iter() {
+        let page = &mut pages[idx];
+        let stem = page.source.file_stem().and_then(|s| s.to_str()).unwrap_or("").to_string();
+        let (date, slug) = match stem.get(..10).and_then(DateTime::parse) {
+            Some(_) if stem[10..].starts_with('-') => (Some(stem[..10].to_string()), stem[11..].to_string()),
+            _ => (None, stem.clone()),
+        };
+
+        page.front_matter.insert("collection".to_string(), Value::from(name));
+        page.front_matter.entry("slug".to_string()).or_insert_with(|| Value::String(slug));
+        if let Some(date) = date {
+            page.front_matter.entry("date".to_string()).or_insert_with(|| Value::String(date));
+        }
+        // templar pages keep whatever excerpt their front matter gives, see above
+        if !page.front_matter.contains_key("excerpt") && page.is_markdown() {
+            let excerpt = markdown::render_markdown(&markdown_excerpt(&page.body), highlighter);
+            page.front_matter.insert("excerpt".to_string(), Value::String(excerpt.trim_end().to_string()));
+        }
+    }
+
+    // neighbours are linked in ascending order, so `previous` is the older post when sorting by date
+    members.sort_by(|&a, &b| compare(pages[a].front_matter.get(&settings.sort_by), pages[b].front_matter.get(&settings.sort_by)));
+    let summaries : Vec<Value> = members.iter().map(|&idx| summary(&pages[idx])).collect();
+    for (position, &idx) in members.iter().enumerate() {
+        let fields = &mut pages[idx].front_matter;
+        if position > 0 {
+            fields.insert("previous".to_string(), summaries[position - 1].clone());
+        }
+        if let Some(next) = summaries.get(position + 1) {
+            fields.insert("next".to_string(), next.clone());
+        }
+    }
+
+    if settings.reverse {
+        // pages without the field stay last, where `compare` put them
+        let with_field = members.iter().take_while(|&&idx| pages[idx].front_matter.contains_key(&settings.sort_by)).count();
+        members[..with_field].reverse();
+    }
+    members.iter().map(|&idx| pages[idx].value()).collect()
+}
+
+/// The text before `<!-- more -->`, or the first paragraph.
+fn markdown_excerpt(body:&str) -> String {
+    if let Some(idx) = body.find(EXCERPT_SEPARATOR) {
+        return body[..idx].to_string();
+    }
+    body.lines()
+        .skip_while(|line| line.trim().is_empty() || line.starts_with('#'))
+        .take_while(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// What `previous` and `next` hold of a page.
+fn summary(page:&Page) -> Value {
+    let mut fields = BTreeMap::new();
+    for key in ["title", "date", "slug"].iter() {
+        if let Some(value) = page.front_matter.get(*key) {
+            fields.insert(key.to_string(), value.clone());
+        }
+    }
+    fields.insert("url".to_string(), Value::String(page.url.clone()));
+    Value::Object(fields)
+}
+
+/// Dates by when they are, numbers by size and other strings alphabetically. Pages without the
+/// field come last.
+pub fn compare(a: Option<&Value>, b: Option<&Value>) -> Ordering {
+    match (a, b) {
+        (Some(&Value::Number(a)), Some(&Value::Number(b))) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        (Some(&Value::String(ref a)), Some(&Value::String(ref b))) => match (DateTime::parse(a), DateTime::parse(b)) {
+            (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+            _ => a.cmp(b),
+        },
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        _ => Ordering::Equal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::{Path, PathBuf};
+
+    use templar::Value;
+
+    use config::{CollectionSettings, HighlightSettings, SiteConfig};
+    use highlight::Highlighter;
+    use page::Page;
+    use super::collect;
+
+    fn post(config:&SiteConfig, name:&str, date: Option<&str>) -> Page {
+        let mut front_matter = BTreeMap::new();
+        if let Some(date) = date {
+            front_matter.insert("date".to_string(), Value::from(date));
+        }
+        Page::generated(config, &Path::new("/site/blog").join(name), &format!("/blog/{}.html", name), front_matter)
+    }
+
+    #[test]
+    fn pages_without_the_sort_field_stay_last_when_reversed() {
+        let config = SiteConfig::default();
+        let settings = CollectionSettings { directory: PathBuf::from("/site/blog"), sort_by: "date".to_string(), reverse: true };
+        let mut pages = vec![
+            post(&config, "undated", None),
+            post(&config, "older", Some("2020-01-01")),
+            post(&config, "newer", Some("2021-01-01")),
+        ];
+        let highlighter = Highlighter::new(&HighlightSettings::default()).expect("the default highlighter");
+
+        let urls : Vec<String> = collect("posts", &settings, &mut pages, &highlighter).iter()
+            .map(|page| page.lookup("url").and_then(|url| url.as_str()).unwrap_or("").to_string())
+            .collect();
+        assert_eq!(urls, vec!["/blog/newer.html", "/blog/older.html", "/blog/undated.html"]);
+    }
+}
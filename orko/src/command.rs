@@ -1,6 +1,8 @@
 use std::io;
 use std::env;
 use std::thread;
+use std::process;
+use std::path::Path;
 
 use docopt::Docopt;
 use watch;
@@ -9,9 +11,12 @@ use colored::Colorize;
 use build;
 use serve;
 use output;
+use format;
+use convert;
+use highlight;
+use site::Site;
 
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
-const SERVER_ADDRESS: &'static str = "127.0.0.1:9000";
 
 const USAGE: &'static str = "
 Pickels~! 🥒
@@ -21,12 +26,20 @@ Usage:
   pickle serve <name>
   pickle build
   pickle build <name>
+  pickle fmt [--check] <file>...
+  pickle convert <html>
+  pickle ast <template>
+  pickle highlight-css [<theme>]
   pickle (-h | --help)
   pickle --version
 
 Options:
   -h --help     Show this screen.
   --version     Show version.
+  --check       List unformatted files instead of formatting them.
+
+highlight-css prints the stylesheet for code highlighted with classes, in the
+colors of a theme, InspiredGitHub by default.
 ";
 
 pub fn run_docopt() -> io::Result<()> {
@@ -36,22 +49,88 @@ pub fn run_docopt() -> io::Result<()> {
             .parse())
         .unwrap_or_else(|e| e.exit());
 
+    if args.get_bool("fmt") {
+        let check = args.get_bool("--check");
+        let mut unformatted = false;
+        let mut failed = false;
+
+        for file in args.get_vec("<file>") {
+            let path = Path::new(file);
+            match format::format_file(path, check) {
+                Ok(true) => {
+                    unformatted = true;
+                    let l = format!("{} {}", if check { "Unformatted" } else { "Formatted" }, path.display());
+                    println!("{}", l.cyan());
+                },
+                Ok(false) => {},
+                Err(err) => {
+                    failed = true;
+                    output::print_error(path, err);
+                },
+            }
+        }
+
+        if failed || (check && unformatted) {
+            process::exit(1);
+        }
+    }
+
+    if args.get_bool("convert") {
+        let path = Path::new(args.get_str("<html>"));
+        match convert::convert_file(path) {
+            Ok(conversion) => {
+                for warning in conversion.warnings {
+                    let l = format!("warning: {}", warning);
+                    eprintln!("{}", l.yellow());
+                }
+                print!("{}", conversion.source);
+            },
+            Err(err) => {
+                output::print_error(path, err);
+                process::exit(1);
+            },
+        }
+    }
+
+    if args.get_bool("ast") {
+        print_ast(Path::new(args.get_str("<template>")));
+    }
+
+    if args.get_bool("highlight-css") {
+        let theme = match args.get_str("<theme>") {
+            "" => highlight::DEFAULT_THEME,
+            theme => theme,
+        };
+        match highlight::stylesheet(theme) {
+            Ok(css) => print!("{}", css),
+            Err(err) => {
+                let l = format!("error: {}", err);
+                eprintln!("{}", l.red());
+                process::exit(1);
+            },
+        }
+    }
+
     if args.get_bool("serve") || args.get_bool("build") {
         let name = args.get_vec("<name>");
         if name.is_empty() {
             println!("multi site not supported yet");
         } else {
-            let site = args.get_vec("<name>")[0];
+            let site_name = args.get_vec("<name>")[0];
             let pwd = env::current_dir()?;
 
             let mut source_directory = pwd.clone();
-            source_directory.push(site);
+            source_directory.push(site_name);
 
-            let mut target_directory = pwd.clone();
-            target_directory.push("_build");
-            target_directory.push(site);
+            let mut site = Site::load(&source_directory).unwrap_or_else(|err| {
+                let l = format!("{}: {}", source_directory.display(), err);
+                println!("{}", l.red());
+                process::exit(1);
+            });
+            let target_directory = site.config.output.clone();
+            let server_address = site.config.server_address().expect("a validated server address");
 
-            let build_result = build::build(&source_directory, &target_directory);
+            let build_result = build::build_site(&site, &source_directory, &target_directory);
 
             let l = format!("\nBuilding {}", source_directory.to_str().unwrap());
             println!("{}", l.cyan());
@@ -59,13 +138,13 @@ pub fn run_docopt() -> io::Result<()> {
             output::print_summary(&source_directory, build_result);
 
             if args.get_bool("serve") {
-                let l = format!("\nServing {} at http://{}\n", site, SERVER_ADDRESS);
+                let l = format!("\nServing {} at http://{}\n", site_name, server_address);
                 println!("{}", l.cyan());
 
                 let server_root = target_directory.clone();
-                let _ = thread::spawn(|| {
+                let _ = thread::spawn(move || {
                     let _ = serve::serve(serve::ServerConfig {
-                        addr: SERVER_ADDRESS.parse().unwrap(),
+                        addr: server_address,
                         root_dir: server_root,
                     });
                 });
@@ -75,11 +154,21 @@ pub fn run_docopt() -> io::Result<()> {
                     match watcher.change_events.recv() {
                         Ok(watch::ChangeEvent{ path, op:_, cookie:_ }) => {
                             if let Some(_) = path {
-                                let l = format!("\nRebuilding {} at http://{}\n", site, SERVER_ADDRESS);
+                                let l = format!("\nRebuilding {} at http://{}\n", site_name, server_address);
                                 println!("{}", l.cyan());
 
-                                let build_result = build::build(&source_directory, &target_directory);
-                                output::print_summary(&source_directory, build_result);
+                                // the server keeps its address and output directory until restarted
+                                match site.reload() {
+                                    Ok(reloaded) => {
+                                        site = reloaded;
+                                        let build_result = build::build_site(&site, &source_directory, &target_directory);
+                                        output::print_summary(&source_directory, build_result);
+                                    },
+                                    Err(err) => {
+                                        let l = format!("{}: {}", source_directory.display(), err);
+                                        println!("{}", l.red());
+                                    },
+                                }
                             }
                         },
                         Err(_) => break 'fs,
@@ -91,3 +180,34 @@ pub fn run_docopt() -> io::Result<()> {
 
     Ok(())
 }
+
+/// Prints the syntax tree of a template as JSON, exiting with an error when it doesn't parse.
+#[cfg(feature = "ast")]
+fn print_ast(path:&Path) {
+    use serde_json;
+    use templar;
+    use build::BuildErrorReason;
+    use page;
+
+    let source = build::read_path(path).unwrap_or_else(|err| {
+        output::print_error(path, err);
+        process::exit(1);
+    });
+    let parsed = templar::parse::parse_recovering(&page::blank_front_matter(&source));
+    match serde_json::to_string_pretty(&parsed) {
+        Ok(json) => println!("{}", json),
+        Err(err) => {
+            output::print_error(path, BuildErrorReason::IO(err.into()));
+            process::exit(1);
+        },
+    }
+    if !parsed.errors.is_empty() {
+        process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "ast"))]
+fn print_ast(_path:&Path) {
+    println!("{}", "orko was built without the ast feature".red());
+    process::exit(1);
+}
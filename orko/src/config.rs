@@ -0,0 +1,416 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Component, Path, PathBuf};
+
+use sass_rs;
+use serde_json;
+use highlight;
+use templar::Value;
+use toml;
+
+/// The configuration file read from the root of a site.
+pub const CONFIG_FILE: &'static str = "orko.toml";
+
+/// Settings for one site, from its `orko.toml`. Every field is optional:
+///
+/// ```toml
+/// output = "_build/blog"       # relative to the directory holding the site, by default
+///                              # _build/<site> in the directory orko runs in
+/// ignore = [".*", "_*"]        # file names that are not built
+/// layouts = "_layouts"         # where the layouts named in front matter are, relative to the site
+/// data = "_data"               # data files for `site.data`, relative to the site
+/// drafts = false               # whether pages with `draft = true` are built
+///
+/// [server]
+/// host = "127.0.0.1"
+/// port = 9000
+///
+/// [templar]
+/// indent = 2
+/// minify = false
+///
+/// [sass]
+/// output_style = "nested"      # nested, expanded, compact or compressed
+/// precision = 5
+/// include_paths = ["_sass"]    # relative to the site
+///
+/// [highlight]
+/// style = "classes"            # classes, styled by `orko highlight-css`, or inline styles
+/// theme = "InspiredGitHub"     # the colors of inline styles
+///
+/// [collections.posts]
+/// directory = "blog"           # relative to the site, the collection's name by default
+/// sort_by = "date"             # a front matter field
+/// reverse = true               # newest first, false for fields such as a chapter number
+///
+/// [taxonomies.tags]             # the terms pages list in their `tags` front matter
+/// layout = "tag"               # renders each term's page, with the term as `term`
+/// index_layout = "tags"        # renders the page listing every term as `terms`, none by default
+/// path = "/tags/"              # terms go to /tags/<term>/ and the index to /tags/, by default the taxonomy's name
+///
+/// [markdown.layouts]
+/// "." = "page"                 # layouts for Markdown pages that don't name one in their front matter,
+/// blog = "post"                # by directory relative to the site, the deepest directory wins
+///
+/// [variables]
+/// title = "My blog"            # available to every template
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiteConfig {
+    pub output: PathBuf, // absolute once loaded
+    pub ignore: Vec<String>,
+    pub layouts: PathBuf, // absolute once loaded
+    pub data: PathBuf, // absolute once loaded
+    pub drafts: bool,
+    pub server: ServerSettings,
+    pub templar: TemplarSettings,
+    pub sass: SassSettings,
+    pub highlight: HighlightSettings,
+    pub markdown: MarkdownSettings,
+    pub collections: BTreeMap<String, CollectionSettings>,
+    pub taxonomies: BTreeMap<String, TaxonomySettings>,
+    pub variables: BTreeMap<String, toml::Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TemplarSettings {
+    pub indent: usize,
+    pub minify: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SassSettings {
+    pub output_style: SassOutputStyle,
+    pub precision: usize,
+    pub include_paths: Vec<PathBuf>, // absolute once loaded
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HighlightSettings {
+    pub style: HighlightStyle,
+    pub theme: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HighlightStyle {
+    Classes,
+    Inline,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CollectionSettings {
+    pub directory: PathBuf, // absolute once loaded
+    pub sort_by: String,
+    pub reverse: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TaxonomySettings {
+    pub layout: String,
+    pub index_layout: Option<String>,
+    pub path: String, // the url of the index, the terms are below it
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarkdownSettings {
+    pub layouts: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SassOutputStyle {
+    Nested,
+    Expanded,
+    Compact,
+    Compressed,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    IO(io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ConfigError::IO(ref err) => write!(f, "could not read {}: {}", CONFIG_FILE, err),
+            &ConfigError::Parse(ref err) => write!(f, "{}: {}", CONFIG_FILE, err),
+            &ConfigError::Invalid(ref reason) => write!(f, "{}: {}", CONFIG_FILE, reason),
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        ConfigError::IO(err)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(err: toml::de::Error) -> Self {
+        ConfigError::Parse(err)
+    }
+}
+
+impl Default for SiteConfig {
+    fn default() -> SiteConfig {
+        SiteConfig {
+            output: PathBuf::new(),
+            ignore: vec![".*".to_string(), "_*".to_string()],
+            layouts: PathBuf::from("_layouts"),
+            data: PathBuf::from("_data"),
+            drafts: false,
+            server: ServerSettings::default(),
+            templar: TemplarSettings::default(),
+            sass: SassSettings::default(),
+            highlight: HighlightSettings::default(),
+            markdown: MarkdownSettings::default(),
+            collections: BTreeMap::new(),
+            taxonomies: BTreeMap::new(),
+            variables: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for ServerSettings {
+    fn default() -> ServerSettings {
+        ServerSettings {
+            host: "127.0.0.1".to_string(),
+            port: 9000,
+        }
+    }
+}
+
+impl Default for TemplarSettings {
+    fn default() -> TemplarSettings {
+        TemplarSettings {
+            indent: 2,
+            minify: false,
+        }
+    }
+}
+
+impl Default for SassSettings {
+    fn default() -> SassSettings {
+        SassSettings {
+            output_style: SassOutputStyle::Nested,
+            precision: 5,
+            include_paths: Vec::new(),
+        }
+    }
+}
+
+impl Default for HighlightSettings {
+    fn default() -> HighlightSettings {
+        HighlightSettings {
+            style: HighlightStyle::Classes,
+            theme: highlight::DEFAULT_THEME.to_string(),
+        }
+    }
+}
+
+impl Default for CollectionSettings {
+    fn default() -> CollectionSettings {
+        CollectionSettings {
+            directory: PathBuf::new(),
+            sort_by: "date".to_string(),
+            reverse: true,
+        }
+    }
+}
+
+/// `_build/<site>` in the working directory, `<site>` being the site directory as given on the
+/// command line. A site given as `..` or outside the working directory has no such place.
+fn default_output(site_directory:&Path) -> Result<PathBuf, ConfigError> {
+    let working_directory = env::current_dir()?;
+    let site = site_directory.strip_prefix(&working_directory).ok()
+        .filter(|site| site.components().next().is_some() && site.components().all(|c| match c { Component::Normal(_) => true, _ => false }));
+    match site {
+        Some(site) => Ok(working_directory.join("_build").join(site)),
+        None => Err(ConfigError::Invalid(format!("no default output for {}, set output in {}", site_directory.display(), CONFIG_FILE))),
+    }
+}
+
+impl SiteConfig {
+    /// Reads the configuration of the site in `site_directory`, the defaults when it has no `orko.toml`.
+    pub fn load(site_directory:&Path) -> Result<SiteConfig, ConfigError> {
+        let path = site_directory.join(CONFIG_FILE);
+        let mut config : SiteConfig = if path.exists() {
+            toml::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            SiteConfig::default()
+        };
+
+        let parent = site_directory.parent().unwrap_or(site_directory);
+        config.output = if config.output.as_os_str().is_empty() {
+            default_output(site_directory)?
+        } else {
+            parent.join(&config.output)
+        };
+        config.layouts = site_directory.join(&config.layouts);
+        config.data = site_directory.join(&config.data);
+        for (name, collection) in config.collections.iter_mut() {
+            let directory = if collection.directory.as_os_str().is_empty() { PathBuf::from(name) } else { collection.directory.clone() };
+            collection.directory = site_directory.join(directory);
+        }
+        for (name, taxonomy) in config.taxonomies.iter_mut() {
+            if taxonomy.path.is_empty() {
+                taxonomy.path = format!("/{}/", name);
+            }
+        }
+        config.sass.include_paths = config.sass.include_paths.iter().map(|p| site_directory.join(p)).collect();
+
+        config.validate(site_directory)?;
+        Ok(config)
+    }
+
+    fn validate(&self, site_directory:&Path) -> Result<(), ConfigError> {
+        if self.output.starts_with(site_directory) {
+            return Err(ConfigError::Invalid(format!("output {} is inside the site directory", self.output.display())));
+        }
+        if let Some(pattern) = self.ignore.iter().find(|p| p.is_empty() || p.contains('/')) {
+            return Err(ConfigError::Invalid(format!("ignore pattern {:?} should be a non empty file name pattern", pattern)));
+        }
+        if let Some(path) = self.sass.include_paths.iter().find(|p| !p.is_dir()) {
+            return Err(ConfigError::Invalid(format!("sass include path {} is not a directory", path.display())));
+        }
+        if let Some((name, collection)) = self.collections.iter().find(|&(_, c)| !c.directory.is_dir()) {
+            return Err(ConfigError::Invalid(format!("the directory {} of collection {} does not exist", collection.directory.display(), name)));
+        }
+        for (name, taxonomy) in self.taxonomies.iter() {
+            if !taxonomy.path.starts_with('/') || !taxonomy.path.ends_with('/') || taxonomy.path.contains("..") {
+                return Err(ConfigError::Invalid(format!("the path of taxonomy {} should start and end with /, such as /{}/", name, name)));
+            }
+            if taxonomy.layout.is_empty() {
+                return Err(ConfigError::Invalid(format!("taxonomy {} needs a layout for its terms", name)));
+            }
+            if let Some(layout) = Some(&taxonomy.layout).into_iter().chain(taxonomy.index_layout.iter()).find(|l| !self.layout_path(l).exists()) {
+                return Err(ConfigError::Invalid(format!("there is no layout {} at {}", layout, self.layout_path(layout).display())));
+            }
+        }
+        if let Some(layout) = self.markdown.layouts.values().find(|l| !self.layout_path(l).exists()) {
+            return Err(ConfigError::Invalid(format!("there is no layout {} at {}", layout, self.layout_path(layout).display())));
+        }
+        self.server_address()?;
+        Ok(())
+    }
+
+    pub fn server_address(&self) -> Result<SocketAddr, ConfigError> {
+        let invalid = || ConfigError::Invalid(format!("{}:{} is not a valid server address", self.server.host, self.server.port));
+        (self.server.host.as_str(), self.server.port).to_socket_addrs().map_err(|_| invalid())?.next().ok_or_else(invalid)
+    }
+
+    /// Whether a file or directory is left out of the build, by the name of its last component.
+    pub fn ignores(&self, path:&Path) -> bool {
+        match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name == CONFIG_FILE || self.ignore.iter().any(|pattern| wildcard_match(pattern, name)),
+            None => true,
+        }
+    }
+
+    pub fn layout_path(&self, layout:&str) -> PathBuf {
+        self.layouts.join(layout).with_extension("templar")
+    }
+
+    /// The layout for a Markdown page without one in its front matter, by its path in the site.
+    pub fn markdown_layout(&self, relative_path:&Path) -> Option<&str> {
+        self.markdown.layouts.iter()
+            .filter(|&(directory, _)| directory == "." || relative_path.starts_with(directory))
+            .max_by_key(|&(directory, _)| if directory == "." { 0 } else { Path::new(directory).components().count() })
+            .map(|(_, layout)| layout.as_str())
+    }
+
+    /// The `[variables]` table, as template variables.
+    pub fn variables(&self) -> BTreeMap<String, Value> {
+        self.variables.iter().map(|(k, v)| (k.clone(), toml_value(v))).collect()
+    }
+
+    pub fn indent_size(&self) -> usize {
+        if self.templar.minify { 0 } else { self.templar.indent }
+    }
+
+    pub fn sass_options(&self) -> sass_rs::Options {
+        sass_rs::Options {
+            output_style: match self.sass.output_style {
+                SassOutputStyle::Nested => sass_rs::OutputStyle::Nested,
+                SassOutputStyle::Expanded => sass_rs::OutputStyle::Expanded,
+                SassOutputStyle::Compact => sass_rs::OutputStyle::Compact,
+                SassOutputStyle::Compressed => sass_rs::OutputStyle::Compressed,
+            },
+            precision: self.sass.precision,
+            include_paths: self.sass.include_paths.iter().map(|p| p.to_string_lossy().into_owned()).collect(),
+            ..sass_rs::Options::default()
+        }
+    }
+}
+
+/// Converts TOML to a template value, dates become strings such as `2024-03-05T10:30:00Z`.
+pub fn toml_value(value:&toml::Value) -> Value {
+    match value {
+        &toml::Value::String(ref s) => Value::String(s.clone()),
+        &toml::Value::Integer(i) => Value::from(i),
+        &toml::Value::Float(f) => Value::Number(f),
+        &toml::Value::Boolean(b) => Value::Bool(b),
+        &toml::Value::Datetime(ref d) => Value::String(d.to_string()),
+        &toml::Value::Array(ref items) => Value::Array(items.iter().map(toml_value).collect()),
+        &toml::Value::Table(ref table) => Value::Object(table.iter().map(|(k, v)| (k.clone(), toml_value(v))).collect()),
+    }
+}
+
+/// Converts JSON, or YAML read as JSON, to a template value.
+pub fn json_value(value:&serde_json::Value) -> Value {
+    match value {
+        &serde_json::Value::Null => Value::Null,
+        &serde_json::Value::Bool(b) => Value::Bool(b),
+        &serde_json::Value::Number(ref n) => n.as_f64().map_or(Value::Null, Value::Number),
+        &serde_json::Value::String(ref s) => Value::String(s.clone()),
+        &serde_json::Value::Array(ref items) => Value::Array(items.iter().map(json_value).collect()),
+        &serde_json::Value::Object(ref fields) => Value::Object(fields.iter().map(|(k, v)| (k.clone(), json_value(v))).collect()),
+    }
+}
+
+/// Matches a name against a pattern where `*` is any run of characters and `?` any one character.
+fn wildcard_match(pattern:&str, name:&str) -> bool {
+    let pattern : Vec<char> = pattern.chars().collect();
+    let name : Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None; // position after the last `*` and the name position it matched up to
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p + 1, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = backtrack {
+            p = star_p;
+            n = star_n + 1;
+            backtrack = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
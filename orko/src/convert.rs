@@ -0,0 +1,35 @@
+use std::path::Path;
+
+use templar;
+use templar::{Node, TemplateContext};
+use templar::html::Conversion;
+use templar::output::{DirectiveHandler, OutputMode, Sink};
+
+use build::{read_path, BuildErrorReason, DirectiveError};
+use format::INDENT_SIZE;
+
+/// Converted HTML never contains directives, so meeting one while rendering is an error.
+struct NoDirectives;
+
+impl DirectiveHandler for NoDirectives {
+    type DirectiveError = DirectiveError;
+
+    fn handle(&mut self, _context:&mut TemplateContext, command: &str, _children: &[Node], _base_indent:usize, _indent_size: usize, _sink: &mut Sink) -> Result<(), DirectiveError> {
+        Err(DirectiveError {
+            directive: command.to_string(),
+            reason: "converted templates have no directives".to_string(),
+            stack: Vec::new(),
+        })
+    }
+}
+
+/// Converts an HTML file to templar, making sure the result parses and renders.
+pub fn convert_file(path:&Path) -> Result<Conversion, BuildErrorReason> {
+    let html = read_path(path)?;
+    let conversion = templar::html::convert(&html, INDENT_SIZE)?;
+
+    let nodes = templar::parse::parse(&conversion.source)?;
+    templar::output::render_to_vec(nodes.as_slice(), &TemplateContext::empty(), OutputMode::Html, 0, INDENT_SIZE, &mut NoDirectives)?;
+
+    Ok(conversion)
+}
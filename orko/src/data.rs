@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use csv;
+use serde_json;
+use serde_yaml;
+use templar::Value;
+use toml;
+
+use config::{json_value, toml_value};
+
+/// A data file that could not be read.
+#[derive(Debug)]
+pub struct DataError {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.reason)
+    }
+}
+
+/// Reads every JSON, TOML, YAML and CSV file under `directory` into a tree keyed by file and
+/// directory names, so `_data/team/people.csv` becomes `team.people`. Other files are skipped.
+pub fn load_data(directory:&Path) -> Result<BTreeMap<String, Value>, DataError> {
+    let mut data = BTreeMap::new();
+    if !directory.is_dir() {
+        return Ok(data);
+    }
+
+    let error = |path:&Path, reason: String| DataError { path: path.to_path_buf(), reason };
+    let entries = fs::read_dir(directory).map_err(|e| error(directory, format!("{}", e)))?;
+    let mut paths : Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    paths.sort();
+
+    for path in paths {
+        let name = match path.file_stem().and_then(|s| s.to_str()) {
+            Some(name) if !name.starts_with('.') => name.to_string(),
+            _ => continue,
+        };
+
+        let value = if path.is_dir() {
+            Value::Object(load_data(&path)?)
+        } else {
+            let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+            if !["json", "toml", "yaml", "yml", "csv"].contains(&extension) {
+                continue;
+            }
+            let text = fs::read_to_string(&path).map_err(|e| error(&path, format!("{}", e)))?;
+            parse_data(extension, &text).map_err(|reason| error(&path, reason))?
+        };
+
+        if data.insert(name.clone(), value).is_some() {
+            return Err(error(&path, format!("there is more than one data file or directory named {}", name)));
+        }
+    }
+    Ok(data)
+}
+
+fn parse_data(extension:&str, text:&str) -> Result<Value, String> {
+    match extension {
+        "json" => serde_json::from_str::<serde_json::Value>(text).map(|v| json_value(&v)).map_err(|e| format!("{}", e)),
+        "toml" => toml::from_str::<toml::Value>(text).map(|v| toml_value(&v)).map_err(|e| format!("{}", e)),
+        "csv" => parse_csv(text),
+        _ => serde_yaml::from_str::<serde_json::Value>(text).map(|v| json_value(&v)).map_err(|e| format!("{}", e)),
+    }
+}
+
+/// A CSV file becomes a list of objects keyed by the header row, every field a string.
+fn parse_csv(text:&str) -> Result<Value, String> {
+    let mut reader = csv::Reader::from_reader(text.as_bytes());
+    let headers = reader.headers().map_err(|e| format!("{}", e))?.clone();
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| format!("{}", e))?;
+        let row = headers.iter().zip(record.iter()).map(|(h, field)| (h.to_string(), Value::from(field))).collect();
+        rows.push(Value::Object(row));
+    }
+    Ok(Value::Array(rows))
+}
@@ -0,0 +1,39 @@
+use serde_json;
+use templar::{Filters, Value};
+
+/// The filters every template orko builds can use, templar's standard ones plus orko's own.
+pub fn site_filters() -> Filters {
+    let mut filters = Filters::standard();
+    filters.register("json", |input, _| {
+        serde_json::to_string(&to_json(input)).map(Value::String).map_err(|e| format!("{}", e))
+    });
+    filters.register("strip_html", |input, _| Ok(Value::String(strip_html(&input.to_string()))));
+    filters
+}
+
+/// The JSON form of a template value, numbers that JSON can't hold become `null`.
+fn to_json(value:&Value) -> serde_json::Value {
+    match value {
+        &Value::Null => serde_json::Value::Null,
+        &Value::Bool(b) => serde_json::Value::Bool(b),
+        &Value::Number(n) => serde_json::Number::from_f64(n).map_or(serde_json::Value::Null, serde_json::Value::Number),
+        &Value::String(ref s) => serde_json::Value::String(s.clone()),
+        &Value::Array(ref items) => serde_json::Value::Array(items.iter().map(to_json).collect()),
+        &Value::Object(ref fields) => serde_json::Value::Object(fields.iter().map(|(k, v)| (k.clone(), to_json(v))).collect()),
+    }
+}
+
+/// Text with its tags left out, for descriptions and feed summaries made from markup.
+fn strip_html(html:&str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {},
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,26 @@
+use std::path::Path;
+
+use templar;
+
+use build::{read_path, write_to_path, BuildErrorReason};
+use page::{blank_front_matter, split_front_matter};
+
+pub const INDENT_SIZE: usize = 2;
+
+/// Rewrites a templar file in the canonical style, returns whether it was not formatted already.
+/// When `check` is set the file is left alone. Front matter is kept as it is.
+pub fn format_file(path:&Path, check: bool) -> Result<bool, BuildErrorReason> {
+    let source = read_path(path)?;
+    let (front_matter, _) = split_front_matter(&source).map_err(BuildErrorReason::FrontMatter)?;
+    let parsed = templar::parse::parse_recovering(&blank_front_matter(&source));
+    if !parsed.errors.is_empty() {
+        return Err(BuildErrorReason::TemplarParse(parsed.errors));
+    }
+
+    let formatted = front_matter.to_string() + &templar::format::format(&parsed.nodes, INDENT_SIZE);
+    let changed = formatted != source;
+    if changed && !check {
+        write_to_path(&formatted, path)?;
+    }
+    Ok(changed)
+}
@@ -0,0 +1,107 @@
+use pulldown_cmark::escape::escape_html;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{css_for_theme_with_class_style, styled_line_to_highlighted_html, ClassStyle, ClassedHTMLGenerator, IncludeBackground};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+use syntect;
+
+use config::{HighlightSettings, HighlightStyle};
+
+/// Classes are prefixed so the theme's rules can't clash with the site's own.
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+pub const DEFAULT_THEME: &'static str = "InspiredGitHub";
+
+/// Highlights code at build time, with classes for a stylesheet from `stylesheet` or with inline styles.
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+    style: HighlightStyle,
+}
+
+impl Highlighter {
+    pub fn new(settings:&HighlightSettings) -> Result<Highlighter, String> {
+        Ok(Highlighter {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme: load_theme(&settings.theme)?,
+            style: settings.style,
+        })
+    }
+
+    /// A `<pre><code>` block, with the code in highlighted spans when the language is known.
+    pub fn highlight(&self, code:&str, language:&str) -> String {
+        let mut code = code.to_string();
+        if !code.ends_with('\n') {
+            code.push('\n');
+        }
+
+        let highlighted = match self.syntaxes.find_syntax_by_token(language).filter(|_| !language.is_empty()) {
+            Some(syntax) => self.spans(&code, syntax).ok(),
+            None => None,
+        };
+        let (pre, spans) = match highlighted {
+            Some(spans) => (self.pre(), spans),
+            None => ("<pre>".to_string(), escaped(&code)),
+        };
+
+        if language.is_empty() {
+            format!("{}<code>{}</code></pre>", pre, spans)
+        } else {
+            format!("{}<code class=\"language-{}\">{}</code></pre>", pre, escaped(language), spans)
+        }
+    }
+
+    fn spans(&self, code:&str, syntax:&SyntaxReference) -> Result<String, syntect::Error> {
+        match self.style {
+            HighlightStyle::Classes => {
+                let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, &self.syntaxes, CLASS_STYLE);
+                for line in LinesWithEndings::from(code) {
+                    generator.parse_html_for_line_which_includes_newline(line)?;
+                }
+                Ok(generator.finalize())
+            },
+            HighlightStyle::Inline => {
+                let mut lines = HighlightLines::new(syntax, &self.theme);
+                let mut out = String::with_capacity(code.len() * 4);
+                for line in LinesWithEndings::from(code) {
+                    let regions = lines.highlight_line(line, &self.syntaxes)?;
+                    out.push_str(&styled_line_to_highlighted_html(&regions, IncludeBackground::No)?);
+                }
+                Ok(out)
+            },
+        }
+    }
+
+    fn pre(&self) -> String {
+        match self.style {
+            HighlightStyle::Classes => "<pre class=\"hl-code\">".to_string(),
+            HighlightStyle::Inline => match self.theme.settings.background {
+                Some(c) => format!("<pre style=\"background-color:#{:02x}{:02x}{:02x};\">", c.r, c.g, c.b),
+                None => "<pre>".to_string(),
+            },
+        }
+    }
+}
+
+/// The stylesheet for code highlighted with classes, in the colors of a theme.
+pub fn stylesheet(theme_name:&str) -> Result<String, String> {
+    css_for_theme_with_class_style(&load_theme(theme_name)?, CLASS_STYLE).map_err(|e| format!("{}", e))
+}
+
+fn load_theme(name:&str) -> Result<Theme, String> {
+    let mut themes = ThemeSet::load_defaults().themes;
+    match themes.remove(name) {
+        Some(theme) => Ok(theme),
+        None => {
+            let names : Vec<&str> = themes.keys().map(|k| k.as_str()).collect();
+            Err(format!("there is no highlighting theme {:?}, the themes are {}", name, names.join(", ")))
+        },
+    }
+}
+
+fn escaped(text:&str) -> String {
+    let mut out = String::with_capacity(text.len());
+    escape_html(&mut out, text).expect("writing to a string");
+    out
+}
@@ -1,3 +1,6 @@
+#[macro_use]
+extern crate serde_derive;
+
 extern crate templar;
 extern crate sass_rs;
 extern crate docopt;
@@ -6,12 +9,31 @@ extern crate staticfile;
 extern crate iron;
 extern crate mount;
 extern crate notify;
-extern crate pad;
 extern crate colored;
+extern crate serde_json;
+extern crate serde;
+extern crate serde_yaml;
+extern crate csv;
+extern crate pulldown_cmark;
+extern crate syntect;
+extern crate toml;
 
 pub mod command;
 pub mod watch;
 
 mod build;
+mod format;
+mod convert;
 mod serve;
 mod output;
+mod filters;
+mod config;
+mod page;
+mod site;
+mod data;
+mod markdown;
+mod highlight;
+mod collection;
+mod pagination;
+mod records;
+mod taxonomy;
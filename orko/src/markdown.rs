@@ -0,0 +1,80 @@
+use std::collections::BTreeSet;
+
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Options, Parser, Tag};
+use templar::filters::slugify;
+
+use highlight::Highlighter;
+
+/// Renders Markdown to HTML with GFM tables, footnotes, task lists and strikethrough. Every
+/// heading gets an id from its text, `## Getting started` becomes `<h2 id="getting-started">`,
+/// and code blocks are highlighted in the language their fence names.
+pub fn render_markdown(text:&str, highlighter:&Highlighter) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+
+    let mut events = Vec::new();
+    let mut heading : Option<Vec<Event>> = None; // the events of the heading being read
+    let mut ids = BTreeSet::new();
+    let mut code : Option<(String, String)> = None; // the language and text of the code block being read
+
+    for event in Parser::new_ext(text, options) {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info.split_whitespace().next().unwrap_or("").to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                code = Some((language, String::new()));
+            },
+            Event::End(Tag::CodeBlock(_)) => {
+                if let Some((language, text)) = code.take() {
+                    events.push(Event::Html(CowStr::from(highlighter.highlight(&text, &language) + "\n")));
+                }
+            },
+            Event::Text(ref text) if code.is_some() => {
+                if let Some((_, ref mut code_text)) = code {
+                    code_text.push_str(text);
+                }
+            },
+            Event::Start(Tag::Heading(..)) => heading = Some(Vec::new()),
+            Event::End(Tag::Heading(level, ..)) => {
+                let inner = heading.take().unwrap_or_default();
+                let id = unique_id(&mut ids, &slugify(&heading_text(&inner)));
+                events.push(Event::Html(CowStr::from(format!("<{} id=\"{}\">", level, id))));
+                events.extend(inner);
+                events.push(Event::Html(CowStr::from(format!("</{}>\n", level))));
+            },
+            event => match heading {
+                Some(ref mut inner) => inner.push(event),
+                None => events.push(event),
+            },
+        }
+    }
+
+    let mut out = String::with_capacity(text.len() * 3 / 2);
+    html::push_html(&mut out, events.into_iter());
+    out
+}
+
+fn heading_text(events:&[Event]) -> String {
+    events.iter().filter_map(|e| match e {
+        &Event::Text(ref text) | &Event::Code(ref text) => Some(text.as_ref()),
+        _ => None,
+    }).collect()
+}
+
+/// Numbers repeated ids, the second `## Usage` gets `usage-1`.
+fn unique_id(ids:&mut BTreeSet<String>, slug:&str) -> String {
+    let base = if slug.is_empty() { "section" } else { slug };
+    let mut id = base.to_string();
+    let mut n = 0;
+    while ids.contains(&id) {
+        n += 1;
+        id = format!("{}-{}", base, n);
+    }
+    ids.insert(id.clone());
+    id
+}
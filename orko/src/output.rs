@@ -2,7 +2,6 @@ use std::io;
 use build::*;
 use std::path::Path;
 use colored::Colorize;
-use pad::PadStr;
 
 //ScanDirectory,
 //Copy(PathBuf),
@@ -32,49 +31,8 @@ pub fn print_summary(path:&Path, result: io::Result<Vec<ProcessedFile>>) {
                 // println!("{}", line.color(color));
 
                 if let Some(err) = file.result.err() {
-                    match err {
-                        BuildErrorReason::IO(io) => {
-                            let line = format!("IO error {:?}", io).red();
-                            println!("{}\n", line);
-                        },
-                        BuildErrorReason::Sass(sass_reason) => {
-                            let line = format!("Sass compilation error {:?}", sass_reason).red();
-                            println!("{}\n", line);
-                        },
-                        BuildErrorReason::TemplarParse(parse_error) => {
-                            println!("Templar compilation error:");
-                            for (idx, c) in parse_error.context.iter().enumerate() {
-                                let line_number = parse_error.line_number + 2 + idx - parse_error.context.len();
-                                let padded_line_number = format!("{}:", line_number).pad_to_width(5);
-
-                                let marker = if parse_error.line_number == line_number {
-                                    ">"
-                                } else { " " };
-
-                                let line = format!("{}{} {}", marker, padded_line_number, c);
-                                println!("{}", line);
-                            }
-
-                            println!("reason -> {:?}\n", parse_error.reason);
-                        },
-                        BuildErrorReason::TemplarWrite(write_error) => {
-                            match write_error {
-                                ::templar::output::WriteError::DirectiveError(e) => {
-
-                                    let error_message = format!("Templar error:\n  {}\n  Command: ={}\n  Reason: {}", file.source.into_os_string().into_string().unwrap(), e.directive, e.reason).red();
-                                    println!("{}\n", error_message);
-                                },
-                                ::templar::output::WriteError::IO(_) => {}
-                            }
-                        },
-                        BuildErrorReason::UTF8Error(utf8_error) => {
-                            let line = format!("File was not UTF8 {:?}", utf8_error).red();
-                            println!("{}\n", line);
-                        },
-                    }
+                    print_error(&file.source, err);
                 }
-
-
             }
         }
         Err(io_error) => {
@@ -82,4 +40,59 @@ pub fn print_summary(path:&Path, result: io::Result<Vec<ProcessedFile>>) {
             println!("{}", line.red());
         }
     }
-}
\ No newline at end of file
+}
+
+pub fn print_error(source:&Path, err: BuildErrorReason) {
+    match err {
+        BuildErrorReason::IO(io) => {
+            let line = format!("IO error {:?}", io).red();
+            println!("{}\n", line);
+        },
+        BuildErrorReason::Sass(sass_reason) => {
+            let line = format!("Sass compilation error {:?}", sass_reason).red();
+            println!("{}\n", line);
+        },
+        BuildErrorReason::TemplarParse(parse_errors) => {
+            println!("Templar compilation error{} in {}:", if parse_errors.len() == 1 { "" } else { "s" }, source.display());
+            for parse_error in parse_errors {
+                println!("{}\n", parse_error);
+            }
+        },
+        BuildErrorReason::TemplarWrite(write_error) => {
+            match write_error {
+                ::templar::output::WriteError::DirectiveError(e) => {
+
+                    let error_message = format!("Templar error:\n  {}\n  Command: ={}\n  Reason: {}", source.display(), e.directive, e.reason).red();
+                    println!("{}", error_message);
+
+                    for frame in e.stack.iter() {
+                        let location = match frame.line {
+                            Some(line) => format!("{}:{}", frame.file.display(), line),
+                            None => frame.file.display().to_string(),
+                        };
+                        let line = if frame.directive.is_empty() {
+                            format!("    in {}", location)
+                        } else {
+                            format!("    at ={} ({})", frame.directive, location)
+                        };
+                        println!("{}", line.red());
+                    }
+                    println!("");
+                },
+                ::templar::output::WriteError::IO(_) => {},
+                ::templar::output::WriteError::Expression(e) => {
+                    let error_message = format!("Templar error:\n  {}\n  Expression: {}\n  Reason: {}\n", source.display(), e.expression, e.reason).red();
+                    println!("{}", error_message);
+                },
+            }
+        },
+        BuildErrorReason::UTF8Error(utf8_error) => {
+            let line = format!("File was not UTF8 {:?}", utf8_error).red();
+            println!("{}\n", line);
+        },
+        BuildErrorReason::FrontMatter(reason) => {
+            let line = format!("Front matter error in {}:\n  {}", source.display(), reason).red();
+            println!("{}\n", line);
+        },
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+use serde_json;
+use serde_yaml;
+use templar::Value;
+use templar::filters::DateTime;
+use templar::output::OutputMode;
+use toml;
+
+use build::{read_path, BuildErrorReason};
+use config::{json_value, toml_value, SiteConfig};
+
+/// A templar page and what its front matter says about it.
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub source: PathBuf,
+    pub output: PathBuf,
+    pub mode: OutputMode,
+    pub url: String, // from the root of the site, such as `/blog/hello.html`
+    pub front_matter: BTreeMap<String, Value>,
+    pub body: String, // the template with its front matter blanked out, so line numbers still match
+}
+
+impl Page {
+    /// Reads the page at `source`, which the build would otherwise write to `destination`.
+    pub fn read(config:&SiteConfig, source:&Path, destination:&Path) -> Result<Page, BuildErrorReason> {
+        let text = read_path(source)?;
+        let (block, _) = split_front_matter(&text).map_err(BuildErrorReason::FrontMatter)?;
+        let front_matter = parse_front_matter(block).and_then(check_front_matter).map_err(BuildErrorReason::FrontMatter)?;
+
+        let (mode, output, url) = match front_matter.get("permalink") {
+            Some(&Value::String(ref permalink)) => {
+                let (mode, output) = permalink_output(config, permalink);
+                (mode, output, permalink.clone())
+            },
+            _ => {
+                let (mode, output) = templar_output(destination);
+                let url = match output.strip_prefix(&config.output) {
+                    Ok(relative) => relative.components().fold(String::new(), |url, c| url + "/" + &c.as_os_str().to_string_lossy()),
+                    Err(_) => output.display().to_string(), // modules are built outside of the site's output
+                };
+                (mode, output, url)
+            },
+        };
+
+        Ok(Page {
+            source: source.to_path_buf(),
+            output,
+            mode,
+            url,
+            front_matter,
+            body: blank_front_matter(&text),
+        })
+    }
+
+    /// A page with no file of its own, such as a taxonomy term's, rendered from the layout at `source`.
+    pub fn generated(config:&SiteConfig, source:&Path, permalink:&str, front_matter: BTreeMap<String, Value>) -> Page {
+        let (mode, output) = permalink_output(config, permalink);
+        Page {
+            source: source.to_path_buf(),
+            output,
+            mode,
+            url: permalink.to_string(),
+            front_matter,
+            body: String::new(),
+        }
+    }
+
+    pub fn layout(&self) -> Option<&str> {
+        self.front_matter.get("layout").and_then(|l| l.as_str())
+    }
+
+    pub fn is_markdown(&self) -> bool {
+        self.source.extension().map_or(false, |e| e == "md" || e == "markdown")
+    }
+
+    pub fn is_draft(&self) -> bool {
+        self.front_matter.get("draft").map_or(false, |d| d.is_truthy())
+    }
+
+    /// The expression naming the list a page paginates over and how many of its items go on each page.
+    pub fn paginate(&self) -> Option<(&str, usize)> {
+        let paginate = self.front_matter.get("paginate")?;
+        match (paginate.get("items").and_then(|i| i.as_str()), paginate.get("per_page")) {
+            (Some(items), Some(&Value::Number(per_page))) => Some((items, per_page as usize)),
+            _ => None,
+        }
+    }
+
+    /// The expression naming the records a page is rendered once for and the permalink pattern each goes to.
+    pub fn generate(&self) -> Option<(&str, &str)> {
+        let generate = self.front_matter.get("generate")?;
+        match (generate.get("items").and_then(|i| i.as_str()), generate.get("permalink").and_then(|p| p.as_str())) {
+            (Some(items), Some(permalink)) => Some((items, permalink)),
+            _ => None,
+        }
+    }
+
+    /// A copy of the page written to `permalink` instead.
+    pub fn moved_to(&self, config:&SiteConfig, permalink:&str) -> Page {
+        let (mode, output) = permalink_output(config, permalink);
+        Page { output, mode, url: permalink.to_string(), ..self.clone() }
+    }
+
+    /// The front matter with the page's `url`, as the `page` variable and in `site.pages`.
+    pub fn value(&self) -> Value {
+        let mut fields = self.front_matter.clone();
+        fields.insert("url".to_string(), Value::String(self.url.clone()));
+        Value::Object(fields)
+    }
+}
+
+/// Picks the output from a double extension: `feed.xml.templar` is rendered as XML to `feed.xml`,
+/// `robots.txt.templar` as text to `robots.txt`, anything else as HTML to a `.html` file.
+pub fn templar_output(destination:&Path) -> (OutputMode, PathBuf) {
+    let stem = destination.with_extension("");
+    match output_mode(&stem) {
+        Some(mode) => (mode, stem),
+        None => (OutputMode::Html, destination.with_extension("html")),
+    }
+}
+
+/// `/about/` is written to `about/index.html` and `/feed.xml` to `feed.xml`, in the site's output.
+fn permalink_output(config:&SiteConfig, permalink:&str) -> (OutputMode, PathBuf) {
+    let mut output = config.output.join(permalink.trim_start_matches('/'));
+    if permalink.ends_with('/') {
+        output.push("index.html");
+    }
+    (output_mode(&output).unwrap_or(OutputMode::Html), output)
+}
+
+fn output_mode(path:&Path) -> Option<OutputMode> {
+    path.extension().and_then(|e| e.to_str()).and_then(OutputMode::for_extension)
+}
+
+/// Splits a template into its front matter block, fences included, and the rest. The block is
+/// `+++` fenced TOML or `---` fenced YAML on the very first line, or empty when there is none.
+pub fn split_front_matter(source:&str) -> Result<(&str, &str), String> {
+    let first_line_end = source.find('\n').map_or(source.len(), |idx| idx + 1);
+    let fence = source[..first_line_end].trim_end();
+    if fence != "+++" && fence != "---" {
+        return Ok(("", source));
+    }
+
+    let mut offset = first_line_end;
+    for line in source[first_line_end..].split_inclusive('\n') {
+        offset += line.len();
+        if line.trim_end() == fence {
+            return Ok(source.split_at(offset));
+        }
+    }
+    Err(format!("the front matter opened with {} on line 1 is never closed", fence))
+}
+
+/// The fields of a front matter block from `split_front_matter`.
+pub fn parse_front_matter(block:&str) -> Result<BTreeMap<String, Value>, String> {
+    let mut lines : Vec<&str> = block.lines().collect();
+    if lines.len() < 2 {
+        return Ok(BTreeMap::new());
+    }
+    let fence = lines.remove(0).trim_end();
+    lines.pop();
+    let content = lines.join("\n");
+
+    if content.trim().is_empty() {
+        Ok(BTreeMap::new())
+    } else if fence == "+++" {
+        match toml::from_str::<toml::Value>(&content).map_err(|e| format!("{}", e))? {
+            toml::Value::Table(table) => Ok(table.iter().map(|(k, v)| (k.clone(), toml_value(v))).collect()),
+            _ => Err("front matter must be a table".to_string()),
+        }
+    } else {
+        let fields = serde_yaml::from_str::<BTreeMap<String, serde_json::Value>>(&content).map_err(|e| format!("{}", e))?;
+        Ok(fields.iter().map(|(k, v)| (k.clone(), json_value(v))).collect())
+    }
+}
+
+/// Replaces the front matter with empty lines, so templar reports errors on the lines of the file.
+pub fn blank_front_matter(source:&str) -> String {
+    match split_front_matter(source) {
+        Ok((block, rest)) => "\n".repeat(block.matches('\n').count()) + rest,
+        Err(_) => source.to_string(),
+    }
+}
+
+/// Checks the fields orko itself uses have the right type.
+fn check_front_matter(front_matter: BTreeMap<String, Value>) -> Result<BTreeMap<String, Value>, String> {
+    for (key, value) in front_matter.iter() {
+        match (key.as_str(), value) {
+            ("title", _) | ("layout", _) if value.as_str().is_none() => return Err(format!("{} should be a string", key)),
+            ("draft", &Value::Bool(_)) => (),
+            ("draft", _) => return Err("draft should be true or false".to_string()),
+            ("date", _) => { DateTime::from_value(value).map_err(|e| format!("date: {}", e))?; },
+            ("tags", &Value::Array(ref tags)) | ("categories", &Value::Array(ref tags)) if tags.iter().all(|t| t.as_str().is_some()) => (),
+            ("tags", _) | ("categories", _) => return Err(format!("{} should be a list of strings", key)),
+            ("permalink", _) if !value.as_str().map_or(false, valid_permalink) => return Err("permalink should be a path starting with /, such as /about/".to_string()),
+            ("paginate", _) if !valid_paginate(value) => return Err("paginate should have items, an expression such as site.collections.posts, and per_page, a whole number above 0".to_string()),
+            ("generate", _) if !valid_generate(value) => return Err("generate should have items, an expression such as site.data.team, and permalink, a pattern such as /team/{slug}/".to_string()),
+            _ => (),
+        }
+    }
+    if front_matter.contains_key("paginate") && front_matter.contains_key("generate") {
+        return Err("a page can either paginate or generate pages, not both".to_string());
+    }
+    Ok(front_matter)
+}
+
+fn valid_paginate(paginate:&Value) -> bool {
+    let items = paginate.get("items").map_or(false, |i| i.as_str().is_some());
+    let per_page = match paginate.get("per_page") {
+        Some(&Value::Number(n)) => n >= 1.0 && n.fract() == 0.0,
+        _ => false,
+    };
+    items && per_page
+}
+
+fn valid_generate(generate:&Value) -> bool {
+    let items = generate.get("items").map_or(false, |i| i.as_str().is_some());
+    let permalink = generate.get("permalink").and_then(|p| p.as_str()).map_or(false, |p| valid_permalink(p) && p.contains('{'));
+    items && permalink
+}
+
+fn valid_permalink(permalink:&str) -> bool {
+    permalink.starts_with('/') && Path::new(permalink).components().all(|c| c != Component::ParentDir)
+}
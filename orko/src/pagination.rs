@@ -0,0 +1,50 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use templar::Value;
+
+use page::Page;
+
+/// Splits `items` into pages of `per_page`, one copy of `page` for each. The first keeps the page's
+/// own output and url, the others go to `page/2/index.html` and so on next to it. Each copy comes
+/// with the `pagination` variable its templates see.
+pub fn paginate(page:&Page, items: Vec<Value>, per_page: usize) -> Vec<(Page, Value)> {
+    // rounded up without adding to per_page, which front matter can make as large as usize allows
+    let total = if items.is_empty() { 1 } else { (items.len() - 1) / per_page + 1 };
+    let base_url = &page.url[..page.url.rfind('/').map_or(0, |idx| idx + 1)];
+    let base_output = page.output.parent().map(Path::to_path_buf).unwrap_or_default();
+    let url = |number: usize| if number == 1 { page.url.clone() } else { format!("{}page/{}/", base_url, number) };
+
+    (1..total + 1).map(|number| {
+        let mut paged = page.clone();
+        if number > 1 {
+            paged.url = url(number);
+            paged.output = base_output.join("page").join(number.to_string()).join("index.html");
+        }
+
+        let mut fields = BTreeMap::new();
+        let page_items = items.iter().skip((number - 1) * per_page).take(per_page).cloned().collect();
+        fields.insert("items".to_string(), Value::Array(page_items));
+        fields.insert("current".to_string(), Value::Number(number as f64));
+        fields.insert("total".to_string(), Value::Number(total as f64));
+        fields.insert("per_page".to_string(), Value::Number(per_page as f64));
+        fields.insert("total_items".to_string(), Value::Number(items.len() as f64));
+        fields.insert("first".to_string(), Value::String(url(1)));
+        fields.insert("last".to_string(), Value::String(url(total)));
+        if number > 1 {
+            fields.insert("previous".to_string(), Value::String(url(number - 1)));
+        }
+        if number < total {
+            fields.insert("next".to_string(), Value::String(url(number + 1)));
+        }
+        (paged, Value::Object(fields))
+    }).collect()
+}
+
+/// Another paginating page whose later pages would be written to the same `page/N` directories,
+/// only the first of them by url gets them.
+pub fn clashing_page<'a>(page:&Page, pages:&'a [Page]) -> Option<&'a Page> {
+    pages.iter().find(|other| {
+        other.url < page.url && other.paginate().is_some() && other.output.parent() == page.output.parent()
+    })
+}
@@ -0,0 +1,48 @@
+use std::collections::BTreeSet;
+
+use templar::Value;
+use templar::filters::slugify;
+
+use config::SiteConfig;
+use page::Page;
+
+/// One copy of `page` for each record, moved to the permalink `pattern` gives it, with the record
+/// its templates see. `{field}` in the pattern is the slug of the record's field, so `/team/{name}/`
+/// puts Ada Lovelace at `/team/ada-lovelace/`.
+pub fn record_pages(config:&SiteConfig, page:&Page, records: Vec<Value>, pattern:&str) -> Result<Vec<(Page, Value)>, String> {
+    let mut permalinks = BTreeSet::new();
+    records.into_iter().enumerate().map(|(idx, record)| {
+        let permalink = expand_permalink(pattern, &record)
+            .map_err(|field| format!("record {} has no {} for the permalink {}", idx + 1, field, pattern))?;
+        if !permalinks.insert(permalink.clone()) {
+            return Err(format!("more than one record has the permalink {}", permalink));
+        }
+        Ok((page.moved_to(config, &permalink), record))
+    }).collect()
+}
+
+/// Fills in the `{field}`s of a permalink pattern, or returns the field a record doesn't have.
+fn expand_permalink(pattern:&str, record:&Value) -> Result<String, String> {
+    let mut permalink = String::with_capacity(pattern.len());
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(idx) => start + idx,
+            None => break,
+        };
+        let field = &rest[start + 1..end];
+        let value = match record.lookup(field) {
+            Some(&Value::String(ref s)) => slugify(s),
+            Some(&Value::Number(n)) => format!("{}", n),
+            _ => String::new(),
+        };
+        if value.is_empty() {
+            return Err(field.to_string());
+        }
+        permalink.push_str(&rest[..start]);
+        permalink.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    permalink.push_str(rest);
+    Ok(permalink)
+}
@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use templar::{TemplateContext, Value};
+
+use config::{ConfigError, SiteConfig};
+use data::{load_data, DataError};
+use collection;
+use filters;
+use highlight::Highlighter;
+use page::Page;
+use taxonomy::{self, Term};
+
+/// A site's configuration and everything its templates can list, gathered before any of it is built.
+///
+/// Templates see it as `site.pages`, `site.collections`, `site.taxonomies` and `site.data`, next to the config's `[variables]`.
+pub struct Site {
+    pub directory: PathBuf,
+    pub config: SiteConfig,
+    pub pages: Vec<Page>, // by url, without drafts unless the config builds them
+    pub context: TemplateContext, // what every page is rendered with
+    pub highlighter: Arc<Highlighter>, // shared with the site reloaded after a change
+    pub taxonomies: BTreeMap<String, Vec<Term>>, // the terms of each configured taxonomy
+}
+
+#[derive(Debug)]
+pub enum SiteError {
+    Config(ConfigError),
+    Data(DataError),
+}
+
+impl fmt::Display for SiteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &SiteError::Config(ref err) => write!(f, "{}", err),
+            &SiteError::Data(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<ConfigError> for SiteError {
+    fn from(err: ConfigError) -> Self {
+        SiteError::Config(err)
+    }
+}
+
+impl From<DataError> for SiteError {
+    fn from(err: DataError) -> Self {
+        SiteError::Data(err)
+    }
+}
+
+impl Site {
+    pub fn load(directory:&Path) -> Result<Site, SiteError> {
+        Site::load_after(directory, None)
+    }
+
+    /// Loads the site again after a change. The highlighter is kept when its settings haven't
+    /// changed, loading every syntax and theme takes longer than the rest of the site.
+    pub fn reload(&self) -> Result<Site, SiteError> {
+        Site::load_after(&self.directory, Some(self))
+    }
+
+    fn load_after(directory:&Path, previous: Option<&Site>) -> Result<Site, SiteError> {
+        let config = SiteConfig::load(directory)?;
+        let data = load_data(&config.data)?;
+        let highlighter = match previous {
+            Some(site) if site.config.highlight == config.highlight => site.highlighter.clone(),
+            _ => Arc::new(Highlighter::new(&config.highlight).map_err(ConfigError::Invalid)?),
+        };
+
+        let mut pages = Vec::new();
+        scan_pages(&config, directory, &config.output, &mut pages);
+        pages.retain(|page| config.drafts || !page.is_draft());
+        pages.sort_by(|a, b| a.url.cmp(&b.url));
+
+        let mut collections = BTreeMap::new();
+        for (name, settings) in config.collections.iter() {
+            collections.insert(name.clone(), Value::Array(collection::collect(name, settings, &mut pages, &highlighter)));
+        }
+
+        let taxonomies : BTreeMap<String, Vec<Term>> = config.taxonomies.iter().map(|(name, settings)| {
+            (name.clone(), taxonomy::terms(name, settings, &pages))
+        }).collect();
+
+        let mut site = BTreeMap::new();
+        // pages that generate others from records aren't written themselves
+        let listed = pages.iter().filter(|page| page.generate().is_none());
+        site.insert("pages".to_string(), Value::Array(listed.map(Page::value).collect()));
+        site.insert("collections".to_string(), Value::Object(collections));
+        site.insert("taxonomies".to_string(), Value::Object(taxonomies.iter().map(|(name, terms)| {
+            (name.clone(), Value::Array(terms.iter().map(Term::value).collect()))
+        }).collect()));
+        site.insert("data".to_string(), Value::Object(data));
+
+        let mut variables = config.variables();
+        variables.insert("site".to_string(), Value::Object(site));
+        let context = TemplateContext::with_data(Value::Object(variables)).with_filters(Arc::new(filters::site_filters()));
+
+        Ok(Site {
+            directory: directory.to_path_buf(),
+            config,
+            pages,
+            context,
+            highlighter,
+            taxonomies,
+        })
+    }
+}
+
+/// Reads the front matter of every templar and Markdown page the build would compile. Pages that can't be read are
+/// left out here, the build reports them.
+fn scan_pages(config:&SiteConfig, directory:&Path, destination:&Path, pages:&mut Vec<Page>) {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        if config.ignores(&path) {
+            continue;
+        }
+        let new_dest = destination.join(path.file_name().expect("a file name"));
+        if path.is_dir() {
+            scan_pages(config, &path, &new_dest, pages);
+        } else if path.extension().map_or(false, |e| e == "templar" || e == "md" || e == "markdown") {
+            if let Ok(page) = Page::read(config, &path, &new_dest) {
+                pages.push(page);
+            }
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use templar::Value;
+use templar::filters::slugify;
+
+use collection::compare;
+use config::TaxonomySettings;
+use page::Page;
+
+/// A term of a taxonomy, such as one tag, and the pages using it.
+#[derive(Debug, Clone)]
+pub struct Term {
+    pub name: String,
+    pub slug: String,
+    pub url: String, // of the term's page, such as `/tags/rust/`
+    pub pages: Vec<Value>, // newest first
+}
+
+impl Term {
+    /// The term as `term` on its own page and in `site.taxonomies`.
+    pub fn value(&self) -> Value {
+        let mut fields = BTreeMap::new();
+        fields.insert("name".to_string(), Value::String(self.name.clone()));
+        fields.insert("slug".to_string(), Value::String(self.slug.clone()));
+        fields.insert("url".to_string(), Value::String(self.url.clone()));
+        fields.insert("count".to_string(), Value::Number(self.pages.len() as f64));
+        fields.insert("pages".to_string(), Value::Array(self.pages.clone()));
+        Value::Object(fields)
+    }
+}
+
+/// Gathers the terms pages list in their `name` front matter field, a string or a list of strings.
+/// Terms with the same slug are one term, named as it was first seen. Returns them ordered by slug.
+/// Pages that generate others from records aren't written themselves, so they have no terms.
+pub fn terms(name:&str, settings:&TaxonomySettings, pages:&[Page]) -> Vec<Term> {
+    let mut terms : BTreeMap<String, Term> = BTreeMap::new();
+    for page in pages.iter().filter(|page| page.generate().is_none()) {
+        let names : Vec<&str> = match page.front_matter.get(name) {
+            Some(&Value::String(ref term)) => vec![term.as_str()],
+            Some(&Value::Array(ref terms)) => terms.iter().filter_map(|t| t.as_str()).collect(),
+            _ => continue,
+        };
+        let mut seen = BTreeSet::new();
+        for term_name in names {
+            let slug = slugify(term_name);
+            if slug.is_empty() || !seen.insert(slug.clone()) {
+                continue;
+            }
+            terms.entry(slug.clone()).or_insert_with(|| Term {
+                name: term_name.to_string(),
+                url: format!("{}{}/", settings.path, slug),
+                slug,
+                pages: Vec::new(),
+            }).pages.push(page.value());
+        }
+    }
+
+    terms.into_iter().map(|(_, mut term)| {
+        // newest first, undated pages last
+        term.pages.sort_by(|a, b| match (a.get("date"), b.get("date")) {
+            (Some(a_date), Some(b_date)) => compare(Some(b_date), Some(a_date)),
+            (a_date, b_date) => compare(a_date, b_date),
+        });
+        term
+    }).collect()
+}
@@ -0,0 +1,29 @@
+// Template generation and timing shared by the benchmarks.
+
+use std::time::Duration;
+
+/// A page of `sections` repeated blocks using every kind of templar line.
+pub fn generate(sections: usize) -> String {
+    let mut source = String::from("doctype html\nhtml\n  head\n    title Generated\n    :css\n      body { margin: 0; }\n  body\n");
+    for i in 0..sections {
+        source.push_str(&format!("    / section {}\n", i));
+        source.push_str(&format!("    section#section-{}.content.wide data-index={} title=\"Section {}\"\n", i, i, i));
+        source.push_str("      h2.title Lorem ipsum dolor sit amet\n");
+        source.push_str("      ul.items\n");
+        for j in 0..5 {
+            source.push_str(&format!("        li.item a href=\"/items/{}/{}\" Item {}\n", i, j, j));
+        }
+        source.push_str("      p\n");
+        source.push_str("        | Consectetur adipiscing elit, sed do eiusmod tempor incididunt.\n");
+        source.push_str("        img src=/image.png alt=\"An image\"\n");
+        source.push_str("      =include partial\n");
+        source.push_str("        .nested#inner\n");
+        source.push_str("      :javascript\n");
+        source.push_str(&format!("        console.log({});\n", i));
+    }
+    source
+}
+
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
@@ -0,0 +1,23 @@
+extern crate templar;
+
+use std::time::Instant;
+
+mod common;
+
+use common::{generate, millis};
+
+fn main() {
+    for &sections in [100, 1_000, 10_000].iter() {
+        let source = generate(sections);
+        let runs = 10;
+
+        let start = Instant::now();
+        for _ in 0..runs {
+            templar::parse::parse(&source).expect("the generated template to parse");
+        }
+        let per_run = millis(start.elapsed()) / runs as f64;
+
+        println!("parse {:>6} sections {:>7} lines {:>9} bytes: {:>9.3} ms/run, {:>7.1} MB/s",
+            sections, source.lines().count(), source.len(), per_run, source.len() as f64 / per_run / 1000.0);
+    }
+}
@@ -0,0 +1,125 @@
+extern crate templar;
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::time::Instant;
+
+use templar::{Node, TemplateContext};
+use templar::escape::escape_default;
+use templar::output::{render, render_to_vec, write_out, DirectiveHandler, OutputMode, Sink};
+
+mod common;
+
+use common::{generate, millis};
+
+/// Renders the children of every directive in place, like an include of an empty partial.
+struct ChildrenHandler;
+
+impl DirectiveHandler for ChildrenHandler {
+    type DirectiveError = ();
+
+    fn handle(&mut self, context:&mut TemplateContext, _command: &str, children: &[Node], base_indent: usize, indent_size: usize, sink: &mut Sink) -> Result<(), ()> {
+        render(children, context, sink, base_indent, indent_size, self).map_err(|_| ())
+    }
+}
+
+/// The renderer before `Sink`, which wrote every piece of a node to the writer as it went, kept
+/// as the baseline the buffered renderer is measured against.
+fn unbuffered_write_out<W: Write>(nodes:&[Node], writer:&mut W, base_indent: usize, indent_size: usize) -> io::Result<()> {
+    for node in nodes {
+        if node.should_indent() {
+            for _ in 0..base_indent {
+                writer.write_all(b" ")?;
+            }
+        }
+
+        match node {
+            Node::Doctype(doctype) => {
+                writer.write_all(format!("<!DOCTYPE {}>\n", doctype).as_bytes())?;
+                writer.write_all(b"\n")?;
+            },
+            Node::Directive { children, .. } => unbuffered_write_out(children, writer, base_indent, indent_size)?,
+            Node::Text(text) => {
+                writer.write_all(text.as_bytes())?;
+                if indent_size > 0 {
+                    writer.write_all(b"\n")?;
+                }
+            },
+            Node::RawText(raw_text) => {
+                writer.write_all(raw_text.as_bytes())?;
+                writer.write_all(b"\n")?;
+            },
+            Node::Comment(_) => {},
+            Node::Element(element) => {
+                let destroy_whitespace = element.name == "a";
+                let seperate_close_tag = !element.children.is_empty() || element.name == "script" || element.name == "a";
+                let trailing_slash = if !seperate_close_tag { " /" } else { "" };
+
+                let open_tag = if element.attributes.is_empty() {
+                    format!("<{}{}>", element.name, trailing_slash)
+                } else {
+                    let attributes : Vec<String> = element.attributes.iter().map(|(k, v)| format!("{}=\"{}\"", k, escape_default(v))).collect();
+                    format!("<{} {}{}>", element.name, attributes.join(" "), trailing_slash)
+                };
+                writer.write_all(open_tag.as_bytes())?;
+                if indent_size > 0 && !destroy_whitespace {
+                    writer.write_all(b"\n")?;
+                }
+                if seperate_close_tag {
+                    if destroy_whitespace {
+                        unbuffered_write_out(&element.children, writer, 0, 0)?;
+                    } else {
+                        unbuffered_write_out(&element.children, writer, base_indent + indent_size, indent_size)?;
+                        for _ in 0..base_indent {
+                            writer.write_all(b" ")?;
+                        }
+                    }
+                    writer.write_all(format!("</{}>", element.name).as_bytes())?;
+                    if indent_size > 0 {
+                        writer.write_all(b"\n")?;
+                    }
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let context = TemplateContext::empty();
+    let path = std::env::temp_dir().join("templar-render-bench.html");
+
+    for &sections in [100, 1_000, 10_000].iter() {
+        let nodes = templar::parse::parse(&generate(sections)).expect("the generated template to parse");
+        let runs = 10;
+
+        let mut bytes = 0;
+        let start = Instant::now();
+        for _ in 0..runs {
+            bytes = render_to_vec(&nodes, &context, OutputMode::Html, 0, 2, &mut ChildrenHandler).expect("rendering").len();
+        }
+        let per_run = millis(start.elapsed()) / runs as f64;
+        println!("render to memory {:>6} sections {:>9} bytes: {:>9.3} ms/run, {:>7.1} MB/s",
+            sections, bytes, per_run, bytes as f64 / per_run / 1000.0);
+
+        let start = Instant::now();
+        for _ in 0..runs {
+            let mut file = File::create(&path).expect("a temporary file");
+            write_out(&nodes, &context, &mut file, OutputMode::Html, 0, 2, &mut ChildrenHandler).expect("rendering");
+        }
+        let per_run = millis(start.elapsed()) / runs as f64;
+        println!("render to file   {:>6} sections {:>9} bytes: {:>9.3} ms/run, {:>7.1} MB/s",
+            sections, bytes, per_run, bytes as f64 / per_run / 1000.0);
+
+        let start = Instant::now();
+        for _ in 0..runs {
+            let mut file = File::create(&path).expect("a temporary file");
+            unbuffered_write_out(&nodes, &mut file, 0, 2).expect("rendering");
+        }
+        let per_run = millis(start.elapsed()) / runs as f64;
+        println!("unbuffered file  {:>6} sections {:>9} bytes: {:>9.3} ms/run, {:>7.1} MB/s",
+            sections, bytes, per_run, bytes as f64 / per_run / 1000.0);
+    }
+
+    let _ = std::fs::remove_file(&path);
+}
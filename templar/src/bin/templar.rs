@@ -0,0 +1,136 @@
+extern crate docopt;
+extern crate serde_json;
+extern crate templar;
+extern crate toml;
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process;
+
+use docopt::Docopt;
+use templar::{Template, Value};
+use templar::output::OutputMode;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const USAGE: &str = "
+Renders a templar template to stdout.
+
+Usage:
+  templar [options] [<template>]
+  templar (-h | --help)
+  templar --version
+
+Reads the template from stdin when no file is given.
+
+Options:
+  -h --help            Show this screen.
+  --version            Show version.
+  -d --data <file>     JSON or TOML file whose fields become template variables.
+  -p --partials <dir>  Directory of .templar files for =include, defaults to the template's directory.
+  -i --indent <n>      Spaces per level of indentation [default: 2].
+  -m --minify          Render without indentation or line breaks.
+  -o --output <mode>   Render as html, xml or text [default: html].
+";
+
+fn main() {
+    let args = Docopt::new(USAGE)
+        .and_then(|dopt| dopt
+            .version(Some(VERSION.to_string()))
+            .parse())
+        .unwrap_or_else(|e| e.exit());
+
+    if let Err(message) = run(&args) {
+        let _ = writeln!(io::stderr(), "error: {}", message);
+        process::exit(1);
+    }
+}
+
+fn run(args:&docopt::ArgvMap) -> Result<(), String> {
+    let template_path = args.get_str("<template>");
+
+    let (source, name) = if template_path.is_empty() {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source).map_err(|e| format!("could not read stdin: {}", e))?;
+        (source, "<stdin>".to_string())
+    } else {
+        (read(Path::new(template_path))?, template_path.to_string())
+    };
+
+    let mode = match args.get_str("--output") {
+        "html" => OutputMode::Html,
+        "xml" => OutputMode::Xml,
+        "text" => OutputMode::Text,
+        other => return Err(format!("--output must be html, xml or text, not {}", other)),
+    };
+
+    let mut template = Template::compile_with(&source, mode).map_err(|e| format!("{}, {}", name, e))?;
+
+    let partials = args.get_str("--partials");
+    let partials_dir = if !partials.is_empty() {
+        Some(Path::new(partials))
+    } else if !template_path.is_empty() {
+        Path::new(template_path).parent().map(|p| if p.as_os_str().is_empty() { Path::new(".") } else { p })
+    } else {
+        None
+    };
+    if let Some(dir) = partials_dir {
+        add_partials(&mut template, dir)?;
+    }
+
+    template.indent_size = if args.get_bool("--minify") {
+        0
+    } else {
+        args.get_str("--indent").parse().map_err(|_| format!("--indent must be a number, not {}", args.get_str("--indent")))?
+    };
+
+    let data = match args.get_str("--data") {
+        "" => Value::object(),
+        path => load_data(Path::new(path))?,
+    };
+
+    let out = template.render(data).map_err(|e| match e {
+        templar::output::WriteError::DirectiveError(e) => format!("{}, {}", name, e),
+        templar::output::WriteError::IO(e) => format!("{}, {}", name, e),
+        templar::output::WriteError::Expression(e) => format!("{}, {}", name, e),
+    })?;
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    stdout.write_all(out.as_bytes()).and_then(|_| stdout.flush()).map_err(|e| format!("could not write output: {}", e))
+}
+
+fn read(path:&Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))
+}
+
+/// Loads the partials the template includes from `dir`, and the ones those include, leaving
+/// every other file alone. A partial without a file is reported when it is rendered.
+fn add_partials(template:&mut Template, dir:&Path) -> Result<(), String> {
+    let mut missing = Vec::new();
+    loop {
+        let needed : Vec<String> = template.missing_partials().into_iter().filter(|name| !missing.contains(name)).collect();
+        if needed.is_empty() {
+            return Ok(());
+        }
+        for name in needed {
+            let path = dir.join(&name).with_extension("templar");
+            if path.is_file() {
+                template.add_partial(&name, &read(&path)?).map_err(|e| format!("{}, {}", path.display(), e))?;
+            } else {
+                missing.push(name);
+            }
+        }
+    }
+}
+
+fn load_data(path:&Path) -> Result<Value, String> {
+    let content = read(path)?;
+    let data = match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => serde_json::from_str(&content).map_err(|e| format!("{}", e)),
+        Some("toml") => toml::from_str(&content).map_err(|e| format!("{}", e)),
+        _ => return Err(format!("{} is not a .json or .toml file", path.display())),
+    };
+    data.map_err(|e| format!("{}, {}", path.display(), e))
+}
@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use {Node, Element};
+use output::OutputMode;
+use parse::{self, ParseError, ParseOptions};
+use template::included_partials;
+
+/// Writes the Rust source for a function returning the nodes of a template, so they are parsed
+/// when the crate is built rather than at runtime. `mode` decides whether HTML's void elements
+/// may have children, as it does for `Template::compile_with`.
+///
+/// This saves parsing, not rendering: the function builds the nodes each time it is called and
+/// they are rendered by walking them, as with a template parsed at runtime.
+pub fn generate(source:&str, fn_name:&str, mode: OutputMode) -> Result<String, ParseError> {
+    Ok(nodes_fn(&parse_for(source, mode)?, fn_name))
+}
+
+/// Generates a module of templates for a build script.
+///
+/// Every `.templar` file directly inside `dir` becomes a function of the same name returning a
+/// `Template`, with the files it `=include`s, and the ones they include, as its partials. A file
+/// named like `feed.xml.templar` is parsed and rendered for XML, and `.txt` for text. The code is
+/// written to `file_name` in cargo's `OUT_DIR`, for use with
+/// `include!(concat!(env!("OUT_DIR"), "/templates.rs"))`.
+///
+/// Templates that fail to parse panic, which cargo reports as a build error, as do file names that
+/// turn into the same function name, such as `blog-post` and `blog_post`.
+pub fn compile_directory(dir:&Path, file_name:&str) -> io::Result<PathBuf> {
+    let out_dir = env::var_os("OUT_DIR").ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "OUT_DIR is not set, compile_directory is for build scripts"))?;
+    let out_path = Path::new(&out_dir).join(file_name);
+
+    println!("cargo:rerun-if-changed={}", dir.display());
+
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "templar") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    // the mode and included partials of each template, by name
+    let mut templates : BTreeMap<String, (OutputMode, Vec<String>)> = BTreeMap::new();
+    let mut fn_names : BTreeMap<String, &Path> = BTreeMap::new();
+    let mut out = String::new();
+    for path in paths.iter() {
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("").to_string();
+        for function in [fn_name(&name), format!("{}_nodes", fn_name(&name))].iter() {
+            if let Some(other) = fn_names.insert(function.clone(), path) {
+                panic!("{} and {} both generate a function named {}, rename one of them", other.display(), path.display(), function);
+            }
+        }
+        let mode = Path::new(&name).extension().and_then(|e| e.to_str()).and_then(OutputMode::for_extension).unwrap_or(OutputMode::Html);
+        let source = fs::read_to_string(path)?;
+        match parse_for(&source, mode) {
+            Ok(nodes) => {
+                out.push_str(&nodes_fn(&nodes, &format!("{}_nodes", fn_name(&name))));
+                let mut includes = Vec::new();
+                included_partials(&nodes, &mut includes);
+                templates.insert(name, (mode, includes));
+            },
+            Err(err) => panic!("{}", describe_error(path, &err)),
+        }
+    }
+
+    for (name, &(mode, _)) in templates.iter() {
+        writeln!(out, "#[allow(dead_code)]").unwrap();
+        writeln!(out, "pub fn {}() -> ::templar::Template {{", fn_name(name)).unwrap();
+        writeln!(out, "    let mut template = ::templar::Template::from_nodes({}_nodes());", fn_name(name)).unwrap();
+        writeln!(out, "    template.mode = ::templar::output::OutputMode::{:?};", mode).unwrap();
+        for partial in partials_of(name, &templates) {
+            writeln!(out, "    template.add_partial_nodes({:?}, {}_nodes());", partial, fn_name(partial)).unwrap();
+        }
+        writeln!(out, "    template\n}}").unwrap();
+    }
+
+    fs::write(&out_path, out)?;
+    Ok(out_path)
+}
+
+fn parse_for(source:&str, mode: OutputMode) -> Result<Vec<Node>, ParseError> {
+    let options = ParseOptions { void_elements: mode == OutputMode::Html, ..ParseOptions::default() };
+    let mut parsed = parse::parse_with(source, options);
+    if parsed.errors.is_empty() {
+        Ok(parsed.nodes)
+    } else {
+        Err(parsed.errors.remove(0))
+    }
+}
+
+/// The templates in the directory that a template includes, directly or through another partial.
+/// Includes of files that aren't there are left for rendering to report.
+fn partials_of<'a>(name:&str, templates:&'a BTreeMap<String, (OutputMode, Vec<String>)>) -> Vec<&'a str> {
+    let mut found : Vec<&str> = Vec::new();
+    let mut pending : Vec<&str> = templates.get(name).map_or(Vec::new(), |t| t.1.iter().map(|s| s.as_str()).collect());
+    while let Some(partial) = pending.pop() {
+        if let Some((key, (_, includes))) = templates.get_key_value(partial) {
+            if !found.contains(&key.as_str()) {
+                found.push(key);
+                pending.extend(includes.iter().map(|s| s.as_str()));
+            }
+        }
+    }
+    found.sort();
+    found
+}
+
+fn nodes_fn(nodes:&[Node], fn_name:&str) -> String {
+    let mut out = String::new();
+    writeln!(out, "#[allow(dead_code)]").unwrap();
+    writeln!(out, "pub fn {}() -> Vec<::templar::Node> {{", fn_name).unwrap();
+    out.push_str("    ");
+    nodes_expr(nodes, 1, &mut out);
+    out.push_str("\n}\n");
+    out
+}
+
+/// A parse error as the message of a build failure.
+pub fn describe_error(path:&Path, err:&ParseError) -> String {
+    format!("{}, {}", path.display(), err)
+}
+
+/// Turns a file name such as `blog-post` into a function name such as `blog_post`.
+fn fn_name(name:&str) -> String {
+    let mut out : String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' }).collect();
+    if out.is_empty() || out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert(0, '_');
+    }
+    out
+}
+
+fn nodes_expr(nodes:&[Node], depth: usize, out:&mut String) {
+    if nodes.is_empty() {
+        out.push_str("Vec::new()");
+        return;
+    }
+
+    out.push_str("vec![\n");
+    for node in nodes {
+        indent(depth + 1, out);
+        node_expr(node, depth + 1, out);
+        out.push_str(",\n");
+    }
+    indent(depth, out);
+    out.push(']');
+}
+
+fn node_expr(node:&Node, depth: usize, out:&mut String) {
+    match node {
+        Node::Doctype(doctype) => write!(out, "::templar::Node::Doctype({:?}.to_string())", doctype).unwrap(),
+        Node::Directive { command, children } => {
+            write!(out, "::templar::Node::Directive {{ command: {:?}.to_string(), children: ", command).unwrap();
+            nodes_expr(children, depth, out);
+            out.push_str(" }");
+        },
+        Node::Text(text) => write!(out, "::templar::Node::Text({:?}.to_string())", text).unwrap(),
+        Node::RawText(text) => write!(out, "::templar::Node::RawText({:?}.to_string())", text).unwrap(),
+        Node::Comment(comment) => write!(out, "::templar::Node::Comment({:?}.to_string())", comment).unwrap(),
+        Node::Element(element) => element_expr(element, depth, out),
+    }
+}
+
+fn element_expr(element:&Element, depth: usize, out:&mut String) {
+    write!(out, "::templar::Node::Element(::templar::Element {{ name: {:?}.to_string(), attributes: vec![", element.name).unwrap();
+    for (k, v) in element.attributes.iter() {
+        write!(out, "({:?}.to_string(), {:?}.to_string()), ", k, v).unwrap();
+    }
+    out.push_str("], children: ");
+    nodes_expr(&element.children, depth, out);
+    out.push_str(" })");
+}
+
+fn indent(depth: usize, out:&mut String) {
+    for _ in 0..depth {
+        out.push_str("    ");
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use {Node, TemplateContext, Value};
+use escape::escape_html;
+use expression::describe;
+use output::{render, DirectiveHandler, OutputMode, Sink, WriteError};
+use parse::is_identifier;
+
+/// An error raised by one of the standard directives.
+#[derive(Debug)]
+pub struct DirectiveError {
+    pub directive: String,
+    pub reason: String,
+}
+
+impl fmt::Display for DirectiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "={}: {}", self.directive, self.reason)
+    }
+}
+
+/// Renders the directives every templar template can use:
+///
+/// * `=doctype` writes the HTML5 doctype, in HTML mode only
+/// * `=yield` renders the nodes given to the template by whatever included it, `=yield name`
+///   the content added to the region `name`, wherever in the document that happens
+/// * `=content_for name` adds the output of its children to the region `name`
+/// * `=if expression` and `=unless expression` render their children depending on whether the value is truthy
+/// * `=each name in expression` renders its children once for each item of an array, with the item
+///   bound to `name` and `loop.index`, `loop.index0`, `loop.first`, `loop.last` and `loop.length` set
+/// * `=set name = expression` sets a variable for the rest of the template
+/// * `=capture name` sets a variable to the rendered output of its children, for `!{name}`
+/// * `=code language`, which is what a `:code language` block parses to, writes its lines in a
+///   `<pre><code>` as they are. Handlers that can highlight code handle it themselves
+///
+/// Returns `None` for any other command, so handlers can fall back on this for what they don't handle themselves.
+pub fn standard<DH>(handler:&mut DH, context:&mut TemplateContext, command:&str, children:&[Node], base_indent: usize, indent_size: usize, sink:&mut Sink) -> Option<Result<(), WriteError<DH::DirectiveError>>>
+    where DH: DirectiveHandler, DH::DirectiveError: From<DirectiveError> {
+    let command = command.trim();
+    let (keyword, argument) = match command.find(char::is_whitespace) {
+        Some(idx) => (&command[..idx], command[idx..].trim()),
+        None => (command, ""),
+    };
+
+    let result = match keyword {
+        "doctype" => {
+            if sink.mode() == OutputMode::Html {
+                sink.write_str("<!DOCTYPE html>\n");
+            }
+            Ok(())
+        },
+        "yield" if !argument.is_empty() => {
+            check_name(command, argument).map(|_| sink.placeholder(argument, base_indent))
+        },
+        "yield" => {
+            let nodes = context.nodes.clone();
+            render(nodes.as_slice(), context, sink, base_indent, indent_size, handler)
+        },
+        "if" | "unless" => {
+            let truthy = match context.evaluate(argument) {
+                Ok(value) => value.is_truthy(),
+                Err(err) => return Some(Err(WriteError::from(err))),
+            };
+            if truthy == (keyword == "if") {
+                render(children, context, sink, base_indent, indent_size, handler)
+            } else {
+                Ok(())
+            }
+        },
+        "each" => each(handler, context, command, children, base_indent, indent_size, sink),
+        "set" => set(context, command, argument),
+        "capture" => capture(handler, context, command, argument, children, indent_size, sink),
+        "content_for" => content_for(handler, context, command, argument, children, indent_size, sink),
+        "code" => {
+            let code = code_text(children);
+            if sink.mode() == OutputMode::Text {
+                sink.write_str(&code);
+                sink.write_str("\n");
+            } else {
+                let class = if argument.is_empty() { String::new() } else { format!(" class=\"language-{}\"", escape_html(argument).expect("escaped language")) };
+                sink.write_indent(base_indent);
+                sink.write_str(&format!("<pre><code{}>{}</code></pre>", class, escape_html(&code).expect("escaped code")));
+                if indent_size > 0 {
+                    sink.write_str("\n");
+                }
+            }
+            Ok(())
+        },
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn each<DH>(handler:&mut DH, context:&mut TemplateContext, command:&str, children:&[Node], base_indent: usize, indent_size: usize, sink:&mut Sink) -> Result<(), WriteError<DH::DirectiveError>>
+    where DH: DirectiveHandler, DH::DirectiveError: From<DirectiveError> {
+    let argument = command["each".len()..].trim();
+    let parts : Vec<&str> = argument.splitn(3, char::is_whitespace).collect();
+    let (name, expression) = match parts.as_slice() {
+        &[name, "in", expression] => (name, expression),
+        _ => return Err(invalid(command, "expected =each name in expression")),
+    };
+
+    let value = context.evaluate(expression)?;
+    let items = match *value {
+        Value::Array(ref items) => items,
+        Value::Null => return Ok(()),
+        ref other => return Err(invalid(command, &format!("{} is {}, not an array", expression, describe(other)))),
+    };
+
+    for (idx, item) in items.iter().enumerate() {
+        let mut loop_vars = BTreeMap::new();
+        loop_vars.insert("index".to_string(), Value::from(idx + 1));
+        loop_vars.insert("index0".to_string(), Value::from(idx));
+        loop_vars.insert("first".to_string(), Value::Bool(idx == 0));
+        loop_vars.insert("last".to_string(), Value::Bool(idx + 1 == items.len()));
+        loop_vars.insert("length".to_string(), Value::from(items.len()));
+
+        let mut vars = BTreeMap::new();
+        vars.insert(name.to_string(), item.clone());
+        vars.insert("loop".to_string(), Value::Object(loop_vars));
+
+        render(children, &mut context.with_vars(vars), sink, base_indent, indent_size, handler)?;
+    }
+    Ok(())
+}
+
+fn set<DE>(context:&mut TemplateContext, command:&str, argument:&str) -> Result<(), WriteError<DE>>
+    where DE: From<DirectiveError> {
+    let (name, expression) = match argument.find('=') {
+        Some(idx) => (argument[..idx].trim(), &argument[idx + 1..]),
+        None => return Err(invalid(command, "expected =set name = expression")),
+    };
+    check_name(command, name)?;
+
+    let value = context.evaluate(expression)?.into_owned();
+    context.set(name, value);
+    Ok(())
+}
+
+fn capture<DH>(handler:&mut DH, context:&mut TemplateContext, command:&str, name:&str, children:&[Node], indent_size: usize, sink:&mut Sink) -> Result<(), WriteError<DH::DirectiveError>>
+    where DH: DirectiveHandler, DH::DirectiveError: From<DirectiveError> {
+    check_name(command, name)?;
+
+    let output = render_separately(handler, context, command, children, indent_size, sink)?;
+    context.set(name, Value::String(output.trim_end_matches('\n').to_string()));
+    Ok(())
+}
+
+fn content_for<DH>(handler:&mut DH, context:&mut TemplateContext, command:&str, name:&str, children:&[Node], indent_size: usize, sink:&mut Sink) -> Result<(), WriteError<DH::DirectiveError>>
+    where DH: DirectiveHandler, DH::DirectiveError: From<DirectiveError> {
+    check_name(command, name)?;
+
+    let output = render_separately(handler, context, command, children, indent_size, sink)?;
+    sink.append_region(name, &output);
+    Ok(())
+}
+
+/// Renders nodes on their own, unindented, rather than into the document. Regions are only filled
+/// once the document is done, so the nodes can't `=yield name` one.
+fn render_separately<DH>(handler:&mut DH, context:&mut TemplateContext, command:&str, children:&[Node], indent_size: usize, sink:&mut Sink) -> Result<String, WriteError<DH::DirectiveError>>
+    where DH: DirectiveHandler, DH::DirectiveError: From<DirectiveError> {
+    let mut separate = Sink::in_memory().with_mode(sink.mode());
+    render(children, context, &mut separate, 0, indent_size, handler)?;
+    if separate.has_placeholders() {
+        return Err(invalid(command, "=yield name can't be used inside it, regions are only filled once the document is done"));
+    }
+    sink.merge_regions(&mut separate);
+    String::from_utf8(separate.into_bytes()).map_err(|_| invalid(command, "the output is not UTF-8"))
+}
+
+/// The text of a `:code` block, one line per child.
+pub fn code_text(children:&[Node]) -> String {
+    let lines : Vec<&str> = children.iter().filter_map(|c| match c {
+        &Node::RawText(ref text) | &Node::Text(ref text) => Some(text.as_str()),
+        _ => None,
+    }).collect();
+    lines.join("\n")
+}
+
+fn check_name<DE>(command:&str, name:&str) -> Result<(), WriteError<DE>>
+    where DE: From<DirectiveError> {
+    let valid = name.chars().next().is_some_and(|c| !c.is_ascii_digit()) && name.chars().all(is_identifier);
+    if valid {
+        Ok(())
+    } else {
+        Err(invalid(command, &format!("{:?} is not a valid variable name", name)))
+    }
+}
+
+fn invalid<DE>(command:&str, reason:&str) -> WriteError<DE>
+    where DE: From<DirectiveError> {
+    WriteError::DirectiveError(DE::from(DirectiveError { directive: command.to_string(), reason: reason.to_string() }))
+}
@@ -25,6 +25,22 @@ pub fn escape_html(raw_str:&str) -> Result<String, FromUtf8Error> {
     String::from_utf8(allocated)
 }
 
+/// Escapes the five characters XML gives a meaning to, leaving everything else as it is.
+pub fn escape_xml(raw_str:&str) -> String {
+    let mut escaped = String::with_capacity(raw_str.len());
+    for c in raw_str.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 pub fn escape_default(raw_str:&str) -> String {
     let mut out : Vec<char> = Vec::new();
     for c in raw_str.chars() {
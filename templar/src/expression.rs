@@ -0,0 +1,442 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+
+use {TemplateContext, Value};
+use parse::is_identifier;
+
+static NULL: Value = Value::Null;
+
+/// An expression used in `#{...}` interpolation and by directives such as `=if`:
+///
+/// * literals: `"text"`, `'text'`, `12`, `-1.5`, `true`, `false`, `null`
+/// * variables, fields and indexes: `page.title`, `posts[0]`, `site.data["authors"]`
+/// * comparisons: `==`, `!=`, `<`, `<=`, `>`, `>=`
+/// * boolean operators: `and`, `or`, `not` (or `&&`, `||`, `!`)
+/// * filters applied left to right: `post.title | truncate(40) | upcase`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Literal(Value),
+    Variable(String),
+    Field(Box<Expression>, String),
+    Index(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Compare(Box<Expression>, Comparison, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Filter { input: Box<Expression>, name: String, args: Vec<Expression> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// An expression which could not be parsed or evaluated.
+#[derive(Debug)]
+pub struct ExpressionError {
+    pub expression: String,
+    pub reason: String,
+}
+
+impl fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} in {:?}", self.reason, self.expression)
+    }
+}
+
+impl Expression {
+    pub fn parse(input:&str) -> Result<Expression, String> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser { tokens, position: 0 };
+        let expression = parser.pipeline()?;
+        match parser.next() {
+            None => Ok(expression),
+            Some(token) => Err(format!("unexpected {}", token)),
+        }
+    }
+
+    /// The value of the expression, borrowed from the context where it can be.
+    pub fn evaluate<'a>(&self, context:&'a TemplateContext) -> Result<Cow<'a, Value>, String> {
+        match self {
+            Expression::Literal(value) => Ok(Cow::Owned(value.clone())),
+            Expression::Variable(name) => Ok(Cow::Borrowed(context.scope.get(name).unwrap_or(&NULL))),
+            Expression::Field(base, name) => {
+                Ok(match base.evaluate(context)? {
+                    Cow::Borrowed(value) => Cow::Borrowed(value.get(name).unwrap_or(&NULL)),
+                    Cow::Owned(value) => Cow::Owned(value.get(name).cloned().unwrap_or(Value::Null)),
+                })
+            },
+            Expression::Index(base, index) => {
+                let index = index.evaluate(context)?;
+                Ok(match base.evaluate(context)? {
+                    Cow::Borrowed(value) => Cow::Borrowed(index_into(value, &index).unwrap_or(&NULL)),
+                    Cow::Owned(value) => Cow::Owned(index_into(&value, &index).cloned().unwrap_or(Value::Null)),
+                })
+            },
+            Expression::Not(inner) => Ok(Cow::Owned(Value::Bool(!inner.evaluate(context)?.is_truthy()))),
+            &Expression::Compare(ref left, comparison, ref right) => {
+                let result = compare(&*left.evaluate(context)?, comparison, &*right.evaluate(context)?)?;
+                Ok(Cow::Owned(Value::Bool(result)))
+            },
+            Expression::And(left, right) => {
+                let result = left.evaluate(context)?.is_truthy() && right.evaluate(context)?.is_truthy();
+                Ok(Cow::Owned(Value::Bool(result)))
+            },
+            Expression::Or(left, right) => {
+                let result = left.evaluate(context)?.is_truthy() || right.evaluate(context)?.is_truthy();
+                Ok(Cow::Owned(Value::Bool(result)))
+            },
+            Expression::Filter { input, name, args } => {
+                let input = input.evaluate(context)?;
+                let mut arg_values = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    arg_values.push(arg.evaluate(context)?.into_owned());
+                }
+                context.filters.apply(name, &input, &arg_values).map(Cow::Owned)
+            },
+        }
+    }
+}
+
+/// Parses and evaluates an expression in one go, as interpolation and directives do.
+pub fn evaluate<'a>(expression:&str, context:&'a TemplateContext) -> Result<Cow<'a, Value>, ExpressionError> {
+    Expression::parse(expression)
+        .and_then(|e| e.evaluate(context))
+        .map_err(|reason| ExpressionError { expression: expression.trim().to_string(), reason })
+}
+
+/// Indexes arrays by number, counting back from the end when negative, and objects by string.
+fn index_into<'a>(value:&'a Value, index:&Value) -> Option<&'a Value> {
+    match (value, index) {
+        (Value::Array(items), &Value::Number(n)) => {
+            let idx = if n < 0.0 { items.len() as f64 + n } else { n };
+            if idx >= 0.0 && idx.fract() == 0.0 { items.get(idx as usize) } else { None }
+        },
+        (Value::Object(fields), Value::String(key)) => fields.get(key),
+        _ => None,
+    }
+}
+
+fn compare(left:&Value, comparison: Comparison, right:&Value) -> Result<bool, String> {
+    match comparison {
+        Comparison::Equal => return Ok(left == right),
+        Comparison::NotEqual => return Ok(left != right),
+        _ => {},
+    }
+
+    let ordering = match (left, right) {
+        (&Value::Number(a), &Value::Number(b)) => a.partial_cmp(&b),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    };
+    let ordering = ordering.ok_or_else(|| format!("cannot compare {} with {}", describe(left), describe(right)))?;
+
+    Ok(match comparison {
+        Comparison::Less => ordering == Ordering::Less,
+        Comparison::LessOrEqual => ordering != Ordering::Greater,
+        Comparison::Greater => ordering == Ordering::Greater,
+        Comparison::GreaterOrEqual => ordering != Ordering::Less,
+        Comparison::Equal | Comparison::NotEqual => unreachable!(),
+    })
+}
+
+/// The kind of a value, for error messages.
+pub fn describe(value:&Value) -> &'static str {
+    match *value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    Number(f64),
+    Str(String),
+    Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Identifier(name) => write!(f, "{}", name),
+            &Token::Number(n) => write!(f, "{}", n),
+            Token::Str(s) => write!(f, "{:?}", s),
+            &Token::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+/// Longest first, so `<=` is not read as `<` followed by `=`.
+const SYMBOLS: [&str; 16] = ["==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "|", ".", ",", "(", ")", "[", "]"];
+
+fn tokenize(input:&str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        let negative_number = c == '-' && rest[1..].starts_with(|c: char| c.is_ascii_digit());
+        if c.is_ascii_digit() || negative_number {
+            let digits = |from: usize| rest[from..].find(|c: char| !c.is_ascii_digit()).map_or(rest.len(), |i| i + from);
+            let mut end = digits(1);
+            // a fraction needs digits after the point, `posts.0.title` is a path
+            if rest[end..].starts_with('.') && rest[end + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+                end = digits(end + 1);
+            }
+            let number = rest[..end].parse::<f64>().map_err(|_| format!("{} is not a number", &rest[..end]))?;
+            tokens.push(Token::Number(number));
+            rest = &rest[end..];
+        } else if c == '"' || c == '\'' {
+            let end = rest[1..].find(c).ok_or_else(|| "a string is never closed".to_string())?;
+            tokens.push(Token::Str(rest[1..end + 1].to_string()));
+            rest = &rest[end + 2..];
+        } else if is_identifier(c) && c != '-' {
+            let end = rest.find(|c| !is_identifier(c)).unwrap_or(rest.len());
+            tokens.push(Token::Identifier(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else {
+            return Err(format!("unexpected {:?}", c));
+        }
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// Takes the next token if it is one of the symbols or keywords given.
+    fn take(&mut self, options: &[&str]) -> Option<String> {
+        let found = match self.peek() {
+            Some(&Token::Symbol(symbol)) if options.contains(&symbol) => symbol.to_string(),
+            Some(Token::Identifier(name)) if options.contains(&name.as_str()) => name.clone(),
+            _ => return None,
+        };
+        self.position += 1;
+        Some(found)
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Symbol(s)) if s == symbol => Ok(()),
+            Some(token) => Err(format!("expected {} but found {}", symbol, token)),
+            None => Err(format!("expected {} at the end", symbol)),
+        }
+    }
+
+    fn pipeline(&mut self) -> Result<Expression, String> {
+        let mut expression = self.or()?;
+        while self.take(&["|"]).is_some() {
+            let name = match self.next() {
+                Some(Token::Identifier(name)) => name,
+                Some(token) => return Err(format!("expected a filter name but found {}", token)),
+                None => return Err("expected a filter name at the end".to_string()),
+            };
+            let mut args = Vec::new();
+            if self.take(&["("]).is_some()
+                && self.take(&[")"]).is_none() {
+                    loop {
+                        args.push(self.pipeline()?);
+                        if self.take(&[","]).is_none() {
+                            break;
+                        }
+                    }
+                    self.expect(")")?;
+                }
+            expression = Expression::Filter { input: Box::new(expression), name, args };
+        }
+        Ok(expression)
+    }
+
+    fn or(&mut self) -> Result<Expression, String> {
+        let mut expression = self.and()?;
+        while self.take(&["or", "||"]).is_some() {
+            expression = Expression::Or(Box::new(expression), Box::new(self.and()?));
+        }
+        Ok(expression)
+    }
+
+    fn and(&mut self) -> Result<Expression, String> {
+        let mut expression = self.not()?;
+        while self.take(&["and", "&&"]).is_some() {
+            expression = Expression::And(Box::new(expression), Box::new(self.not()?));
+        }
+        Ok(expression)
+    }
+
+    fn not(&mut self) -> Result<Expression, String> {
+        if self.take(&["not", "!"]).is_some() {
+            Ok(Expression::Not(Box::new(self.not()?)))
+        } else {
+            self.comparison()
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expression, String> {
+        let left = self.postfix()?;
+        let comparison = match self.take(&["==", "!=", "<", "<=", ">", ">="]) {
+            Some(ref op) if op == "==" => Comparison::Equal,
+            Some(ref op) if op == "!=" => Comparison::NotEqual,
+            Some(ref op) if op == "<" => Comparison::Less,
+            Some(ref op) if op == "<=" => Comparison::LessOrEqual,
+            Some(ref op) if op == ">" => Comparison::Greater,
+            Some(_) => Comparison::GreaterOrEqual,
+            None => return Ok(left),
+        };
+        Ok(Expression::Compare(Box::new(left), comparison, Box::new(self.postfix()?)))
+    }
+
+    fn postfix(&mut self) -> Result<Expression, String> {
+        let mut expression = self.primary()?;
+        loop {
+            if self.take(&["."]).is_some() {
+                match self.next() {
+                    Some(Token::Identifier(name)) => expression = Expression::Field(Box::new(expression), name),
+                    Some(Token::Number(n)) if n >= 0.0 && n.fract() == 0.0 => expression = Expression::Index(Box::new(expression), Box::new(Expression::Literal(Value::Number(n)))),
+                    Some(token) => return Err(format!("expected a field name but found {}", token)),
+                    None => return Err("expected a field name at the end".to_string()),
+                }
+            } else if self.take(&["["]).is_some() {
+                let index = self.pipeline()?;
+                self.expect("]")?;
+                expression = Expression::Index(Box::new(expression), Box::new(index));
+            } else {
+                return Ok(expression);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expression::Literal(Value::Number(n))),
+            Some(Token::Str(s)) => Ok(Expression::Literal(Value::String(s))),
+            Some(Token::Identifier(name)) => Ok(match name.as_str() {
+                "true" => Expression::Literal(Value::Bool(true)),
+                "false" => Expression::Literal(Value::Bool(false)),
+                "null" => Expression::Literal(Value::Null),
+                _ => Expression::Variable(name),
+            }),
+            Some(Token::Symbol("(")) => {
+                let expression = self.pipeline()?;
+                self.expect(")")?;
+                Ok(expression)
+            },
+            Some(token) => Err(format!("unexpected {}", token)),
+            None => Err("expected a value".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn context() -> TemplateContext {
+        let mut post = BTreeMap::new();
+        post.insert("title".to_string(), Value::from("Hello, World"));
+        let mut data = BTreeMap::new();
+        data.insert("posts".to_string(), Value::Array(vec![Value::Object(post), Value::from("second")]));
+        data.insert("count".to_string(), Value::Number(3.0));
+        TemplateContext::with_data(Value::Object(data))
+    }
+
+    fn value(expression: &str) -> Result<Value, String> {
+        let context = context();
+        evaluate(expression, &context).map(|v| v.into_owned()).map_err(|e| e.reason)
+    }
+
+    fn variable(name: &str) -> Box<Expression> {
+        Box::new(Expression::Variable(name.to_string()))
+    }
+
+    #[test]
+    fn a_minus_sign_only_starts_a_number() {
+        assert_eq!(tokenize("x - 1"), Err("unexpected '-'".to_string()));
+        assert_eq!(tokenize("x -1"), Ok(vec![Token::Identifier("x".to_string()), Token::Number(-1.0)]));
+        assert_eq!(Expression::parse("x -1"), Err("unexpected -1".to_string()));
+    }
+
+    #[test]
+    fn digits_after_a_number_and_a_point_are_a_fraction() {
+        // `posts.0.title` is a path, but `posts.0.1` reads the number 0.1, which is not an index
+        assert_eq!(tokenize("posts.0.1"), Ok(vec![Token::Identifier("posts".to_string()), Token::Symbol("."), Token::Number(0.1)]));
+        assert_eq!(value("posts.0.1"), Err("expected a field name but found 0.1".to_string()));
+        assert_eq!(value("posts.0.title"), Ok(Value::from("Hello, World")));
+    }
+
+    #[test]
+    fn strings_must_be_closed() {
+        assert_eq!(tokenize("'open"), Err("a string is never closed".to_string()));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_which_binds_tighter_than_or() {
+        assert_eq!(Expression::parse("not a or b and c"), Ok(Expression::Or(
+            Box::new(Expression::Not(variable("a"))),
+            Box::new(Expression::And(variable("b"), variable("c"))),
+        )));
+    }
+
+    #[test]
+    fn comparisons_bind_tighter_than_not() {
+        assert_eq!(Expression::parse("!a == b"), Ok(Expression::Not(Box::new(Expression::Compare(variable("a"), Comparison::Equal, variable("b"))))));
+    }
+
+    #[test]
+    fn filters_apply_to_the_whole_expression_before_them() {
+        assert_eq!(Expression::parse("a == b | upcase"), Ok(Expression::Filter {
+            input: Box::new(Expression::Compare(variable("a"), Comparison::Equal, variable("b"))),
+            name: "upcase".to_string(),
+            args: Vec::new(),
+        }));
+        assert_eq!(value("count > 2 and (count < 3 or count == 3)"), Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn indexes_count_back_from_the_end_when_negative() {
+        assert_eq!(value("posts[-1]"), Ok(Value::from("second")));
+        assert_eq!(value("posts[5]"), Ok(Value::Null));
+    }
+
+    #[test]
+    fn unknown_filters_are_errors() {
+        assert_eq!(value("count | nope"), Err("there is no filter named nope".to_string()));
+    }
+
+    #[test]
+    fn filter_arguments_are_expressions() {
+        assert_eq!(value("posts[0].title | truncate(count, \"!\")"), Ok(Value::from("He!")));
+        assert_eq!(value("missing | default(posts[1] | upcase)"), Ok(Value::from("SECOND")));
+        assert_eq!(value("posts[0].title | truncate(\"five\")"), Err("truncate: expected a number but got a string".to_string()));
+        assert_eq!(value("count | truncate(1"), Err("expected ) at the end".to_string()));
+    }
+}
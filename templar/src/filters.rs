@@ -0,0 +1,336 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use Value;
+use expression::describe;
+
+/// A filter gets the value piped into it and its arguments, as in `value | name(arg, arg)`.
+pub type Filter = Arc<dyn Fn(&Value, &[Value]) -> Result<Value, String> + Send + Sync>;
+
+/// The filters available to expressions, by name.
+#[derive(Clone)]
+pub struct Filters {
+    filters: BTreeMap<String, Filter>,
+}
+
+impl fmt::Debug for Filters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.filters.keys()).finish()
+    }
+}
+
+impl Default for Filters {
+    fn default() -> Filters {
+        Filters::standard()
+    }
+}
+
+impl Filters {
+    pub fn empty() -> Filters {
+        Filters {
+            filters: BTreeMap::new(),
+        }
+    }
+
+    /// `upcase`, `downcase`, `truncate`, `date`, `default`, `join`, `length` and `slugify`.
+    pub fn standard() -> Filters {
+        let mut filters = Filters::empty();
+        filters.register("upcase", |input, _| Ok(Value::String(input.to_string().to_uppercase())));
+        filters.register("downcase", |input, _| Ok(Value::String(input.to_string().to_lowercase())));
+        filters.register("truncate", truncate);
+        filters.register("date", date);
+        filters.register("default", |input, args| {
+            let fallback = args.first().cloned().unwrap_or(Value::Null);
+            Ok(match input {
+                &Value::Null | &Value::Bool(false) => fallback,
+                Value::String(s) if s.is_empty() => fallback,
+                Value::Array(items) if items.is_empty() => fallback,
+                _ => input.clone(),
+            })
+        });
+        filters.register("join", |input, args| {
+            let separator = args.first().map_or(" ".to_string(), |s| s.to_string());
+            match input {
+                Value::Array(items) => Ok(Value::String(items.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(&separator))),
+                other => Ok(Value::String(other.to_string())),
+            }
+        });
+        filters.register("length", |input, _| {
+            let length = match input {
+                &Value::Null => 0,
+                Value::String(s) => s.chars().count(),
+                Value::Array(items) => items.len(),
+                Value::Object(fields) => fields.len(),
+                other => return Err(format!("{} has no length", describe(other))),
+            };
+            Ok(Value::from(length))
+        });
+        filters.register("slugify", |input, _| Ok(Value::String(slugify(&input.to_string()))));
+        filters
+    }
+
+    /// Adds a filter, replacing any other filter with the same name.
+    pub fn register<F>(&mut self, name:&str, filter: F) where F: Fn(&Value, &[Value]) -> Result<Value, String> + Send + Sync + 'static {
+        self.filters.insert(name.to_string(), Arc::new(filter));
+    }
+
+    pub fn apply(&self, name:&str, input:&Value, args:&[Value]) -> Result<Value, String> {
+        match self.filters.get(name) {
+            Some(filter) => filter(input, args).map_err(|reason| format!("{}: {}", name, reason)),
+            None => Err(format!("there is no filter named {}", name)),
+        }
+    }
+}
+
+/// A number argument, for filters such as `truncate(20)`.
+pub fn number_arg(args:&[Value], idx: usize) -> Result<Option<f64>, String> {
+    match args.get(idx) {
+        None => Ok(None),
+        Some(&Value::Number(n)) => Ok(Some(n)),
+        Some(other) => Err(format!("expected a number but got {}", describe(other))),
+    }
+}
+
+/// Shortens text to at most `length` characters, ending it with `...` or the second argument.
+fn truncate(input:&Value, args:&[Value]) -> Result<Value, String> {
+    let length = number_arg(args, 0)?.ok_or_else(|| "expected a length".to_string())? as usize;
+    let ellipsis = args.get(1).map_or("...".to_string(), |e| e.to_string());
+    let text = input.to_string();
+
+    if text.chars().count() <= length {
+        return Ok(Value::String(text));
+    }
+    let keep = length.saturating_sub(ellipsis.chars().count());
+    let mut out : String = text.chars().take(keep).collect();
+    out.push_str(&ellipsis);
+    Ok(Value::String(out))
+}
+
+/// Lowercases text and joins its words with dashes, for URLs and ids.
+pub fn slugify(text:&str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+    slug
+}
+
+const MONTHS: [&str; 12] = ["January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November", "December"];
+const WEEKDAYS: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+
+/// A moment read from `2024-03-05`, `2024-03-05 10:30`, `2024-03-05T10:30:00Z` or a unix timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32, // 1 to 12
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl DateTime {
+    pub fn from_value(value:&Value) -> Result<DateTime, String> {
+        match value {
+            &Value::Number(n) => Ok(DateTime::from_timestamp(n as i64)),
+            Value::String(s) => DateTime::parse(s).ok_or_else(|| format!("{:?} is not a date", s)),
+            other => Err(format!("expected a date but got {}", describe(other))),
+        }
+    }
+
+    pub fn parse(text:&str) -> Option<DateTime> {
+        let text = text.trim();
+        let number = |from: usize, len: usize| text.get(from..from + len).filter(|s| s.bytes().all(|b| b.is_ascii_digit())).and_then(|s| s.parse::<u32>().ok());
+
+        let year = number(0, 4)?;
+        if text.get(4..5) != Some("-") || text.get(7..8) != Some("-") {
+            return None;
+        }
+        let month = number(5, 2).filter(|m| *m >= 1 && *m <= 12)?;
+        let day = number(8, 2).filter(|d| *d >= 1 && *d <= days_in_month(year as i64, month))?;
+
+        let (hour, minute, second) = match text.get(10..11) {
+            None => (0, 0, 0),
+            Some("T") | Some(" ") => {
+                if text.get(13..14) != Some(":") {
+                    return None;
+                }
+                let second = if text.get(16..17) == Some(":") { number(17, 2)? } else { 0 };
+                (number(11, 2).filter(|h| *h < 24)?, number(14, 2).filter(|m| *m < 60)?, second)
+            },
+            Some(_) => return None,
+        };
+
+        Some(DateTime { year: year as i64, month, day, hour, minute, second })
+    }
+
+    pub fn from_timestamp(timestamp: i64) -> DateTime {
+        let days = timestamp.div_euclid(86400);
+        let seconds = timestamp.rem_euclid(86400) as u32;
+
+        // from Howard Hinnant's civil_from_days
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        DateTime { year, month, day, hour: seconds / 3600, minute: seconds / 60 % 60, second: seconds % 60 }
+    }
+
+    /// Days since 1970-01-01.
+    pub fn days(&self) -> i64 {
+        let year = if self.month <= 2 { self.year - 1 } else { self.year };
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let month = self.month as i64;
+        let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146097 + doe - 719468
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        self.days() * 86400 + (self.hour * 3600 + self.minute * 60 + self.second) as i64
+    }
+
+    /// Formats with `%Y %y %m %d %e %H %M %S %B %b %A %a %j %%`, as in strftime.
+    pub fn format(&self, format:&str) -> String {
+        let mut out = String::new();
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+            let weekday = WEEKDAYS[(self.days() + 3).rem_euclid(7) as usize]; // 1970-01-01 was a thursday
+            let month = MONTHS[self.month as usize - 1];
+            match chars.next() {
+                Some('Y') => out.push_str(&self.year.to_string()),
+                Some('y') => out.push_str(&format!("{:02}", self.year.rem_euclid(100))),
+                Some('m') => out.push_str(&format!("{:02}", self.month)),
+                Some('d') => out.push_str(&format!("{:02}", self.day)),
+                Some('e') => out.push_str(&self.day.to_string()),
+                Some('H') => out.push_str(&format!("{:02}", self.hour)),
+                Some('M') => out.push_str(&format!("{:02}", self.minute)),
+                Some('S') => out.push_str(&format!("{:02}", self.second)),
+                Some('B') => out.push_str(month),
+                Some('b') => out.push_str(&month[..3]),
+                Some('A') => out.push_str(weekday),
+                Some('a') => out.push_str(&weekday[..3]),
+                Some('j') => {
+                    let start = DateTime { month: 1, day: 1, ..*self };
+                    out.push_str(&format!("{:03}", self.days() - start.days() + 1));
+                },
+                Some('%') => out.push('%'),
+                Some(other) => {
+                    out.push('%');
+                    out.push(other);
+                },
+                None => out.push('%'),
+            }
+        }
+        out
+    }
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Formats a date, `%Y-%m-%d` unless a strftime style format is given.
+fn date(input:&Value, args:&[Value]) -> Result<Value, String> {
+    if let &Value::Null = input {
+        return Ok(Value::Null);
+    }
+    let format = args.first().map_or("%Y-%m-%d".to_string(), |f| f.to_string());
+    Ok(Value::String(DateTime::from_value(input)?.format(&format)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(name:&str, input: Value, args: Vec<Value>) -> Result<Value, String> {
+        Filters::standard().apply(name, &input, &args)
+    }
+
+    fn date(text:&str) -> DateTime {
+        DateTime::parse(text).unwrap_or_else(|| panic!("{:?} should parse", text))
+    }
+
+    #[test]
+    fn unknown_filters_are_errors() {
+        assert_eq!(apply("shout", Value::from("a"), vec![]), Err("there is no filter named shout".to_string()));
+    }
+
+    #[test]
+    fn filter_errors_name_the_filter() {
+        assert_eq!(apply("truncate", Value::from("abc"), vec![]), Err("truncate: expected a length".to_string()));
+        assert_eq!(apply("truncate", Value::from("abc"), vec![Value::Bool(true)]), Err("truncate: expected a number but got a boolean".to_string()));
+        assert_eq!(apply("length", Value::Number(1.0), vec![]), Err("length: a number has no length".to_string()));
+    }
+
+    #[test]
+    fn filters_take_arguments() {
+        assert_eq!(apply("truncate", Value::from("abcdefgh"), vec![Value::Number(5.0)]), Ok(Value::from("ab...")));
+        assert_eq!(apply("truncate", Value::from("abc"), vec![Value::Number(5.0)]), Ok(Value::from("abc")));
+        assert_eq!(apply("join", Value::Array(vec![Value::from("a"), Value::from("b")]), vec![Value::from(", ")]), Ok(Value::from("a, b")));
+        assert_eq!(apply("default", Value::from(""), vec![Value::from("x")]), Ok(Value::from("x")));
+        assert_eq!(apply("date", Value::from("2024-03-05"), vec![Value::from("%e %B %Y")]), Ok(Value::from("5 March 2024")));
+    }
+
+    #[test]
+    fn dates_format_back_to_what_was_parsed() {
+        for text in &["2024-02-29 23:59:07", "1999-12-31 00:00:00", "1969-07-20 20:17:40", "1000-01-01 12:00:00"] {
+            assert_eq!(date(text).format("%Y-%m-%d %H:%M:%S"), *text);
+        }
+        assert_eq!(date("2024-03-05T10:30:00Z"), date("2024-03-05 10:30"));
+    }
+
+    #[test]
+    fn timestamps_round_trip() {
+        for timestamp in &[0, 951782400, 1709634600, -14182940, -62135596800] {
+            assert_eq!(DateTime::from_timestamp(*timestamp).timestamp(), *timestamp);
+        }
+        assert_eq!(date("1970-01-01").timestamp(), 0);
+        assert_eq!(DateTime::from_timestamp(1709634600), date("2024-03-05 10:30"));
+    }
+
+    #[test]
+    fn impossible_dates_are_not_parsed() {
+        for text in &["2023-02-29", "2024-13-01", "2024-04-31", "2024-03-05 24:00", "2024-3-5", "2024-03-05x"] {
+            assert_eq!(DateTime::parse(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn weekdays_and_days_of_the_year() {
+        assert_eq!(date("2024-03-05").format("%A %a %j %%"), "Tuesday Tue 065 %");
+        assert_eq!(date("1969-12-31").format("%A %y"), "Wednesday 69");
+    }
+
+    #[test]
+    fn slugs_keep_only_lowercase_words() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  --Rust 2018 -- edition--  "), "rust-2018-edition");
+        assert_eq!(slugify("Crème Brûlée"), "crème-brûlée");
+        assert_eq!(slugify("?!"), "");
+    }
+}
@@ -0,0 +1,192 @@
+use {Node, Element};
+use parse::{self, ParseError, is_identifier, identifier};
+
+/// Turns parsed nodes back into templar source in one canonical style: `indent_size` spaces per
+/// level, `#id` and `.class` shorthand where the values allow it, `div` left out when shorthand is
+/// used, then the remaining attributes in their original order, and a leading text child inlined.
+///
+/// Parsing the result gives back the same nodes. That is why attributes aren't sorted: their order
+/// is the order they are rendered in, so sorting them would change the page, not only its source.
+pub fn format(nodes: &[Node], indent_size: usize) -> String {
+    let mut out = String::new();
+    format_nodes(nodes, 0, indent_size, &mut out);
+    out
+}
+
+/// Parses and formats templar source.
+pub fn format_source(source: &str, indent_size: usize) -> Result<String, ParseError> {
+    parse::parse(source).map(|nodes| format(&nodes, indent_size))
+}
+
+fn format_nodes(nodes: &[Node], indent: usize, indent_size: usize, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Doctype(doctype) => {
+                push_line(out, indent, &format!("doctype {}", doctype));
+            },
+            Node::Directive { command, children } => {
+                if let Some(lines) = code_block_lines(command, children) {
+                    push_line(out, indent, &format!(":{}", command));
+                    for line in lines {
+                        if line.is_empty() {
+                            out.push('\n');
+                        } else {
+                            push_line(out, indent + indent_size, line);
+                        }
+                    }
+                    continue;
+                }
+                push_line(out, indent, &format!("={}", command));
+                format_nodes(children, indent + indent_size, indent_size, out);
+            },
+            &Node::Text(ref text) | &Node::RawText(ref text) => {
+                push_line(out, indent, &prefixed("|", text));
+            },
+            Node::Comment(comment) => {
+                push_line(out, indent, &prefixed("/", comment));
+            },
+            Node::Element(element) => {
+                format_element(element, indent, indent_size, out);
+            },
+        }
+    }
+}
+
+fn format_element(element: &Element, indent: usize, indent_size: usize, out: &mut String) {
+    if let Some(keyword) = raw_block_keyword(element) {
+        push_line(out, indent, keyword);
+        // the first child is the newline the parser opens every block with
+        for child in element.children.iter().skip(1) {
+            if let Node::RawText(text) = child {
+                push_line(out, indent + indent_size, text);
+            }
+        }
+        return;
+    }
+
+    let mut line = element_head(element);
+    let mut children = element.children.as_slice();
+
+    if let Some(Node::Text(text)) = children.first() {
+        if can_inline(element, text) {
+            line.push(' ');
+            line.push_str(text);
+            children = &children[1..];
+        }
+    }
+
+    push_line(out, indent, &line);
+    format_nodes(children, indent + indent_size, indent_size, out);
+}
+
+/// The tag, shorthand id and classes and the other attributes of an element.
+fn element_head(element: &Element) -> String {
+    let mut attributes = element.attributes.as_slice();
+    let mut id = None;
+    let mut class = None;
+
+    // the parser appends shorthand as `id` then `class` after the other attributes
+    if let Some((last, rest)) = attributes.split_last() {
+        if last.0 == "class" && last.1.split(' ').all(is_identifier_str) {
+            class = Some(&last.1);
+            attributes = rest;
+        }
+    }
+    if let Some((last, rest)) = attributes.split_last() {
+        if last.0 == "id" && is_identifier_str(&last.1) {
+            id = Some(&last.1);
+            attributes = rest;
+        }
+    }
+
+    let mut head = String::new();
+    if element.name != "div" || (id.is_none() && class.is_none()) {
+        head.push_str(&element.name);
+    }
+    if let Some(id) = id {
+        head.push('#');
+        head.push_str(id);
+    }
+    if let Some(class) = class {
+        for class_name in class.split(' ') {
+            head.push('.');
+            head.push_str(class_name);
+        }
+    }
+    for (key, value) in attributes {
+        head.push(' ');
+        head.push_str(key);
+        head.push('=');
+        head.push_str(&attribute_value(value));
+    }
+    head
+}
+
+/// `:javascript` and `:css` blocks come out of the parser as these elements holding raw text.
+fn raw_block_keyword(element: &Element) -> Option<&'static str> {
+    let opened_as_block = match element.children.first() {
+        Some(Node::RawText(text)) => text == "\n",
+        _ => false,
+    };
+    let raw = element.children.iter().all(|c| matches!(*c, Node::RawText(_)));
+    if !opened_as_block || !raw {
+        return None;
+    }
+
+    let attributes : Vec<(&str, &str)> = element.attributes.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+    match (element.name.as_str(), attributes.as_slice()) {
+        ("script", &[("type", "text/javascript")]) => Some(":javascript"),
+        ("style", &[]) => Some(":css"),
+        _ => None,
+    }
+}
+
+/// `:code` blocks come out of the parser as `=code` directives holding a raw text node per line.
+fn code_block_lines<'a>(command: &str, children: &'a [Node]) -> Option<Vec<&'a str>> {
+    if command != "code" && !command.starts_with("code ") {
+        return None;
+    }
+    children.iter().map(|c| match c {
+        Node::RawText(text) => Some(text.as_str()),
+        _ => None,
+    }).collect()
+}
+
+/// Text can follow the head on the same line unless the parser would read it as something else.
+fn can_inline(element: &Element, text: &str) -> bool {
+    let looks_like_attribute = match identifier(text) {
+        Some((_, rest)) => rest.starts_with('='),
+        None => false,
+    };
+    !text.is_empty() && text.trim() == text && !looks_like_attribute && element.name != "doctype"
+}
+
+fn attribute_value(value: &str) -> String {
+    // a value can only start with a quote when it was never closed, which is how it is written back
+    let unclosed_quote = value.starts_with('"') && !value[1..].contains('"');
+    if value.is_empty() || (value.contains(char::is_whitespace) && !unclosed_quote) {
+        format!("\"{}\"", value)
+    } else {
+        value.to_string()
+    }
+}
+
+fn is_identifier_str(str: &str) -> bool {
+    !str.is_empty() && str.chars().all(is_identifier)
+}
+
+fn prefixed(prefix: &str, text: &str) -> String {
+    if text.is_empty() {
+        prefix.to_string()
+    } else {
+        format!("{} {}", prefix, text)
+    }
+}
+
+fn push_line(out: &mut String, indent: usize, line: &str) {
+    for _ in 0..indent {
+        out.push(' ');
+    }
+    out.push_str(line);
+    out.push('\n');
+}
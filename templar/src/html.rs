@@ -0,0 +1,409 @@
+use {Node, Element, is_void_element};
+use format::format;
+use parse::{self, ParseError, is_identifier};
+
+/// The text kept for whitespace between inline elements and text, such as `<b>a</b> <i>b</i>`. Templar
+/// trims text, so the space is written as a character reference, which renders the same.
+const SPACE: &str = "&#32;";
+
+/// Templar source converted from HTML, with anything that could not be carried over.
+#[derive(Debug)]
+pub struct Conversion {
+    pub source: String,
+    pub warnings: Vec<String>,
+}
+
+/// Converts an HTML page into templar source, which is parsed again before it is returned.
+pub fn convert(html: &str, indent_size: usize) -> Result<Conversion, ParseError> {
+    let (nodes, warnings) = parse_html(html);
+    let source = format(&nodes, indent_size);
+    parse::parse(&source)?;
+
+    Ok(Conversion {
+        source,
+        warnings,
+    })
+}
+
+/// Reads HTML into templar nodes, leniently: unknown constructs are skipped with a warning and
+/// missing end tags are implied.
+pub fn parse_html(html: &str) -> (Vec<Node>, Vec<String>) {
+    let mut parser = HtmlParser {
+        input: html,
+        roots: Vec::new(),
+        stack: Vec::new(),
+        warnings: Vec::new(),
+    };
+    parser.run();
+    (parser.roots, parser.warnings)
+}
+
+struct HtmlParser<'a> {
+    input: &'a str, // what is left to read
+    roots: Vec<Node>,
+    stack: Vec<Element>, // open elements
+    warnings: Vec<String>,
+}
+
+impl<'a> HtmlParser<'a> {
+    fn run(&mut self) {
+        while !self.input.is_empty() {
+            if self.input.starts_with("<!--") {
+                let content = self.take_until("-->", 4);
+                self.append(Node::Comment(collapse_whitespace(content)));
+            } else if self.input.starts_with("<![CDATA[") {
+                let content = self.take_until("]]>", 9);
+                self.text(content);
+            } else if self.input.starts_with("<!") {
+                let content = self.take_until(">", 2);
+                self.declaration(content);
+            } else if self.input.starts_with("<?") {
+                let content = self.take_until(">", 2);
+                self.warn(format!("skipped processing instruction <?{}>", content.trim_end_matches('?')));
+            } else if self.input.starts_with("</") {
+                let content = self.take_until(">", 2);
+                self.end_tag(content.trim());
+            } else if self.input.starts_with('<') && self.input[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+                self.start_tag();
+            } else {
+                // text runs up to the next thing that looks like markup
+                let first = self.input.chars().next().map_or(1, char::len_utf8);
+                let end = self.input[first..].find('<').map(|i| i + first).unwrap_or(self.input.len());
+                let (text, rest) = self.input.split_at(end);
+                self.input = rest;
+                self.text(text);
+            }
+        }
+
+        while let Some(element) = self.stack.pop() {
+            self.append(Node::Element(element));
+        }
+        trim_space(&mut self.roots);
+    }
+
+    /// Skips `skip` bytes and returns everything up to `end`, leaving the input after `end`.
+    fn take_until(&mut self, end: &str, skip: usize) -> &'a str {
+        let input = &self.input[skip..];
+        match input.find(end) {
+            Some(idx) => {
+                self.input = &input[idx + end.len()..];
+                &input[..idx]
+            },
+            None => {
+                self.input = "";
+                input
+            },
+        }
+    }
+
+    fn warn(&mut self, warning: String) {
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+
+    fn append(&mut self, mut node: Node) {
+        if let Node::Element(ref mut element) = node {
+            trim_space(&mut element.children);
+        }
+        let siblings = match self.stack.last_mut() {
+            Some(parent) => &mut parent.children,
+            None => &mut self.roots,
+        };
+        // a space is only kept when what follows it is inline as well
+        if !is_inline(&node) {
+            trim_space(siblings);
+        }
+        siblings.push(node);
+    }
+
+    fn text(&mut self, text: &str) {
+        let preformatted = self.stack.iter().any(|e| e.name == "pre" || e.name == "textarea");
+        if preformatted {
+            if text.contains('\n') {
+                self.warn("whitespace inside <pre> and <textarea> is not preserved".to_string());
+            }
+            for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                self.append(Node::Text(escape_interpolation(line)));
+            }
+        } else {
+            let collapsed = collapse_whitespace(text);
+            let after_inline = self.last_sibling().is_some_and(|node| is_inline(node) && !is_space(node));
+            if after_inline && (collapsed.is_empty() || text.starts_with(char::is_whitespace)) {
+                self.append(Node::Text(SPACE.to_string()));
+            }
+            if !collapsed.is_empty() {
+                self.append(Node::Text(escape_interpolation(&collapsed)));
+                if text.ends_with(char::is_whitespace) {
+                    self.append(Node::Text(SPACE.to_string()));
+                }
+            }
+        }
+    }
+
+    fn last_sibling(&self) -> Option<&Node> {
+        match self.stack.last() {
+            Some(parent) => parent.children.last(),
+            None => self.roots.last(),
+        }
+    }
+
+    fn declaration(&mut self, content: &str) {
+        let is_doctype = content.get(..7).is_some_and(|s| s.eq_ignore_ascii_case("doctype"));
+        if is_doctype && self.stack.is_empty() {
+            self.append(Node::Doctype(collapse_whitespace(&content[7..])));
+        } else {
+            self.warn(format!("skipped <!{}>", content));
+        }
+    }
+
+    fn start_tag(&mut self) {
+        let input = &self.input[1..];
+        let name_end = input.find(|c: char| c.is_whitespace() || c == '>' || c == '/').unwrap_or(input.len());
+        let name = input[..name_end].to_lowercase();
+        self.input = &input[name_end..];
+
+        let (mut attributes, self_closing) = self.attributes();
+
+        // the id and class go last, where the templar parser puts `#id.class` shorthand
+        for key in ["id", "class"].iter() {
+            if let Some(idx) = attributes.iter().position(|(k, _)| k == key) {
+                let attribute = attributes.remove(idx);
+                attributes.push(attribute);
+            }
+        }
+
+        while self.stack.last().is_some_and(|open| implies_end(&open.name, &name)) {
+            let open = self.stack.pop().expect("an open element");
+            self.append(Node::Element(open));
+        }
+
+        let mut element = Element {
+            name: name.clone(),
+            attributes,
+            children: Vec::new(),
+        };
+
+        if name == "script" || name == "style" {
+            let content = self.raw_content(&name);
+            raw_block(&mut element, content);
+            self.element(element);
+        } else if self_closing || is_void_element(&name) {
+            self.element(element);
+        } else if is_name(&name) {
+            self.stack.push(element);
+        } else {
+            self.warn(format!("dropped <{}> which is not a valid templar tag, keeping its content", name));
+        }
+    }
+
+    /// Adds a finished element, unless its name cannot be written in templar.
+    fn element(&mut self, element: Element) {
+        if is_name(&element.name) {
+            self.append(Node::Element(element));
+        } else {
+            self.warn(format!("dropped <{}> which is not a valid templar tag", element.name));
+        }
+    }
+
+    /// Reads attributes up to the end of a start tag, and whether it closed itself with `/>`.
+    fn attributes(&mut self) -> (Vec<(String, String)>, bool) {
+        let mut attributes = Vec::new();
+
+        loop {
+            let input = self.input.trim_start();
+            if input.is_empty() {
+                self.input = input;
+                return (attributes, false);
+            }
+            if let Some(rest) = input.strip_prefix('>') {
+                self.input = rest;
+                return (attributes, false);
+            }
+            if let Some(rest) = input.strip_prefix("/>") {
+                self.input = rest;
+                return (attributes, true);
+            }
+
+            let name_end = input.find(|c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/').unwrap_or(input.len()).max(1);
+            let name = input[..name_end].to_lowercase();
+            let mut rest = input[name_end..].trim_start();
+
+            let value = if rest.starts_with('=') {
+                rest = rest[1..].trim_start();
+                let quote = rest.chars().next().filter(|&c| c == '"' || c == '\'');
+                match quote {
+                    Some(quote) => {
+                        let end = rest[1..].find(quote).map(|i| i + 1).unwrap_or(rest.len());
+                        let value = &rest[1..end];
+                        rest = if end < rest.len() { &rest[end + 1..] } else { "" };
+                        value
+                    },
+                    None => {
+                        let end = rest.find(|c: char| c.is_whitespace() || c == '>').unwrap_or(rest.len());
+                        let value = &rest[..end];
+                        rest = &rest[end..];
+                        value
+                    },
+                }
+            } else {
+                ""
+            };
+            self.input = rest;
+
+            if !is_name(&name) {
+                if name != "/" {
+                    self.warn(format!("dropped attribute {} which is not a valid templar attribute name", name));
+                }
+                continue;
+            }
+
+            let value = if name == "class" {
+                escape_interpolation(&collapse_whitespace(value))
+            } else {
+                attribute_value(&escape_interpolation(value))
+            };
+            attributes.push((name, value));
+        }
+    }
+
+    /// Everything up to the end tag of a script or style element, which is not parsed as HTML.
+    fn raw_content(&mut self, name: &str) -> &'a str {
+        let end_tag = format!("</{}", name);
+        let idx = self.input.as_bytes()
+            .windows(end_tag.len())
+            .position(|w| w.eq_ignore_ascii_case(end_tag.as_bytes()))
+            .unwrap_or(self.input.len());
+
+        let content = &self.input[..idx];
+        let after = &self.input[idx..];
+        self.input = after.find('>').map(|i| &after[i + 1..]).unwrap_or("");
+        content
+    }
+
+    fn end_tag(&mut self, name: &str) {
+        let name = name.to_lowercase();
+        if !self.stack.iter().any(|e| e.name == name) {
+            if !is_void_element(&name) {
+                self.warn(format!("ignored </{}> which closes nothing", name));
+            }
+            return;
+        }
+
+        while let Some(element) = self.stack.pop() {
+            let done = element.name == name;
+            self.append(Node::Element(element));
+            if done {
+                break;
+            }
+        }
+    }
+}
+
+/// Fills a script or style element so the formatter writes it as a `:javascript` or `:css` block
+/// where it can, and as plain text lines otherwise.
+fn raw_block(element: &mut Element, content: &str) {
+    let lines : Vec<&str> = content.lines().map(str::trim_start).filter(|l| !l.is_empty()).collect();
+    if lines.is_empty() {
+        return;
+    }
+
+    let is_javascript = element.name == "script" && element.attributes.iter().all(|(k, v)| k == "type" && v == "text/javascript");
+    let is_css = element.name == "style" && element.attributes.is_empty();
+
+    if is_javascript || is_css {
+        if is_javascript {
+            element.attributes = vec![("type".to_string(), "text/javascript".to_string())];
+        }
+        element.children.push(Node::RawText("\n".to_string()));
+        element.children.extend(lines.into_iter().map(|l| Node::RawText(l.to_string())));
+    } else {
+        element.children.extend(lines.into_iter().map(|l| Node::Text(l.trim().to_string())));
+    }
+}
+
+/// Text with `#{` and `!{` written as `\#{` and `\!{`, so templar shows them rather than
+/// evaluating them.
+fn escape_interpolation(text: &str) -> String {
+    text.replace("#{", "\\#{").replace("!{", "\\!{")
+}
+
+/// An attribute value as templar can write it. A quoted value ends at its first `"`, so quotes
+/// in a value that needs quoting, or that would be read as quoted, are written as `&quot;`, which
+/// is what they render as anyway.
+fn attribute_value(value: &str) -> String {
+    let value = value.replace(['\n', '\r', '\t'], " ");
+    let read_as_quoted = value.starts_with('"') && value[1..].contains('"');
+    if value.contains('"') && (value.contains(char::is_whitespace) || read_as_quoted) {
+        value.replace('"', "&quot;")
+    } else {
+        value
+    }
+}
+
+/// Elements laid out in a line of text, where the whitespace between them shows.
+fn is_inline(node: &Node) -> bool {
+    match node {
+        Node::Text(_) => true,
+        Node::Element(element) => matches!(element.name.as_str(),
+            "a" | "abbr" | "b" | "bdi" | "bdo" | "br" | "button" | "cite" | "code" | "data" | "dfn" |
+            "em" | "i" | "img" | "input" | "kbd" | "label" | "mark" | "q" | "s" | "samp" | "select" |
+            "small" | "span" | "strong" | "sub" | "sup" | "textarea" | "time" | "u" | "var"),
+        _ => false,
+    }
+}
+
+fn is_space(node: &Node) -> bool {
+    match node {
+        Node::Text(text) => text == SPACE,
+        _ => false,
+    }
+}
+
+/// Drops a space left at the end of a list of nodes, with nothing inline after it.
+fn trim_space(nodes: &mut Vec<Node>) {
+    if nodes.last().is_some_and(is_space) {
+        nodes.pop();
+    }
+}
+
+/// Whether an open element is closed by the start of another, as HTML allows for lists, tables
+/// and paragraphs.
+fn implies_end(open: &str, starting: &str) -> bool {
+    match open {
+        "li" => starting == "li",
+        "dt" | "dd" => starting == "dt" || starting == "dd",
+        "option" => starting == "option" || starting == "optgroup",
+        "tr" => starting == "tr",
+        "td" | "th" => starting == "td" || starting == "th" || starting == "tr",
+        "p" => matches!(starting,
+            "address" | "article" | "aside" | "blockquote" | "div" | "dl" | "fieldset" | "footer" |
+            "form" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "header" | "hr" | "main" | "nav" |
+            "ol" | "p" | "pre" | "section" | "table" | "ul"),
+        _ => false,
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(is_identifier)
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Template, Value};
+
+    #[test]
+    fn interpolation_markers_are_kept_as_text() {
+        let html = "<pre>puts \"#{name}\"</pre>\n<p title=\"!{x}\">a #{b} and \\#{c}</p>";
+        let conversion = convert(html, 2).expect("the conversion to parse");
+        assert_eq!(conversion.source, "pre puts \"\\#{name}\"\np title=\\!{x} a \\#{b} and \\\\#{c}\n");
+
+        let rendered = Template::compile(&conversion.source).expect("a template").render(Value::Null).expect("the template to render");
+        assert_eq!(rendered, "<pre>\n  puts \"#{name}\"\n</pre>\n<p title=\"!{x}\">\n  a #{b} and \\#{c}\n</p>\n");
+    }
+}
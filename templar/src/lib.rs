@@ -1,12 +1,35 @@
+#[cfg(feature = "serialization")]
 #[macro_use]
-extern crate nom;
+extern crate serde_derive;
+#[cfg(feature = "serialization")]
+extern crate serde;
+#[cfg(feature = "serialization")]
+extern crate serde_json;
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::sync::Arc;
 
 pub mod parse;
 pub mod escape;
 pub mod output;
+pub mod format;
+pub mod html;
+pub mod value;
+pub mod expression;
+pub mod filters;
+pub mod directives;
+pub mod template;
+pub mod codegen;
+
+pub use value::Value;
+pub use filters::Filters;
+pub use template::Template;
 
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
 pub struct Element {
     pub name: String,
     pub attributes: Vec<(String, String)>,
@@ -14,11 +37,13 @@ pub struct Element {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
 pub enum Node {
     Doctype(String),
     Directive { command: String, children: Vec<Node> },
     Text(String),
     RawText(String), // for javascript
+    Comment(String), // a `/` comment line, kept for the formatter and never rendered
     Element(Element),
 }
 
@@ -26,27 +51,28 @@ impl Node {
     pub fn should_indent(&self) -> bool {
         match self {
             &Node::Element(_) | &Node::Text(_) | &Node::Doctype(_) => true,
-            &Node::Directive { .. } | &Node::RawText(_) => false,
+            &Node::Directive { .. } | &Node::RawText(_) | &Node::Comment(_) => false,
         }
     }
 
     pub fn supports_children(&self) -> bool {
         match self {
             &Node::Directive { .. } | &Node::Element(_) => true,
-            &Node::Doctype(_) | &Node::Text(_) | &Node::RawText(_) => false,
+            &Node::Doctype(_) | &Node::Text(_) | &Node::RawText(_) | &Node::Comment(_) => false,
         }
     }
 
     pub fn append_child(&mut self, node:Node) -> bool {
-        match self {
-            &mut Node::Doctype(_) => false,
-            &mut Node::Directive { ref mut children, .. } => {
+        match *self {
+            Node::Doctype(_) => false,
+            Node::Directive { ref mut children, .. } => {
                 children.push(node);
                 true
             },
-            &mut Node::Text(_) => false,
-            &mut Node::RawText(_) => false, // for javascript
-            &mut Node::Element(ref mut ele) => {
+            Node::Text(_) => false,
+            Node::RawText(_) => false, // for javascript
+            Node::Comment(_) => false,
+            Node::Element(ref mut ele) => {
                 ele.children.push(node);
                 true
             },
@@ -62,20 +88,101 @@ pub fn element(name:&str, attributes: Vec<(&str, &str)>) -> Element {
     }
 }
 
+/// Elements which never have content or a closing tag in HTML.
+pub fn is_void_element(name:&str) -> bool {
+    matches!(name,
+        "area" | "base" | "br" | "col" | "embed" | "hr" | "img" | "input" |
+        "link" | "meta" | "param" | "source" | "track" | "wbr")
+}
+
 pub fn contains<T, F>(opt: Option<T>, f: F) -> bool where F: Fn(&T) -> bool {
     opt.iter().any(f)
 }
 
 
-#[derive(Debug)]
+/// Variables visible to a template. Directives such as `=each` put a new scope on top of the
+/// one they were used in rather than copying it.
+#[derive(Debug, Clone)]
+pub struct Scope {
+    pub vars: BTreeMap<String, Value>,
+    pub parent: Option<Rc<Scope>>,
+}
+
+impl Scope {
+    pub fn get(&self, name:&str) -> Option<&Value> {
+        match self.vars.get(name) {
+            Some(value) => Some(value),
+            None => self.parent.as_ref().and_then(|parent| parent.get(name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct TemplateContext {
-    pub nodes:Vec<Node>,
+    pub nodes: Rc<Vec<Node>>, // rendered by =yield
+    pub scope: Rc<Scope>,
+    pub filters: Arc<Filters>, // available to expressions
 }
 
 impl TemplateContext {
     pub fn empty() -> TemplateContext {
+        TemplateContext::with_data(Value::Null)
+    }
+
+    /// A context whose variables are the fields of `data`.
+    pub fn with_data(data: Value) -> TemplateContext {
+        let vars = match data {
+            Value::Object(fields) => fields,
+            _ => BTreeMap::new(),
+        };
+        TemplateContext {
+            nodes: Rc::new(Vec::new()),
+            scope: Rc::new(Scope { vars, parent: None }),
+            filters: Arc::new(Filters::standard()),
+        }
+    }
+
+    pub fn with_filters(mut self, filters: Arc<Filters>) -> TemplateContext {
+        self.filters = filters;
+        self
+    }
+
+    /// A context yielding `nodes` which sees the same variables as this one, for `=include`.
+    /// Variables set with it stay in a scope of its own.
+    pub fn yielding(&self, nodes: Vec<Node>) -> TemplateContext {
+        TemplateContext {
+            nodes: Rc::new(nodes),
+            scope: Rc::new(Scope { vars: BTreeMap::new(), parent: Some(self.scope.clone()) }),
+            filters: self.filters.clone(),
+        }
+    }
+
+    /// A context with new variables on top of this one's.
+    pub fn with_vars(&self, vars: BTreeMap<String, Value>) -> TemplateContext {
         TemplateContext {
-            nodes: Vec::new()
+            nodes: self.nodes.clone(),
+            scope: Rc::new(Scope { vars, parent: Some(self.scope.clone()) }),
+            filters: self.filters.clone(),
         }
     }
-}
\ No newline at end of file
+
+    /// Sets a variable in this context's own scope, hiding any of the same name further out.
+    pub fn set(&mut self, name:&str, value: Value) {
+        Rc::make_mut(&mut self.scope).vars.insert(name.to_string(), value);
+    }
+
+    /// The value at a dotted path such as `page.title`, the first part naming a variable.
+    pub fn lookup(&self, path:&str) -> Option<&Value> {
+        let path = path.trim();
+        let (name, rest) = match path.find('.') {
+            Some(idx) => (&path[..idx], &path[idx + 1..]),
+            None => (path, ""),
+        };
+        self.scope.get(name).and_then(|value| value.lookup(rest))
+    }
+
+    /// Evaluates an expression such as `post.title | upcase`, see `expression::Expression`.
+    pub fn evaluate(&self, expression:&str) -> Result<Cow<'_, Value>, expression::ExpressionError> {
+        expression::evaluate(expression, self)
+    }
+}
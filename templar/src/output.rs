@@ -1,13 +1,16 @@
-
 use {Node, TemplateContext};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::io::{self, Write};
 
 use escape::*;
+use expression::ExpressionError;
 
 #[derive(Debug)]
 pub enum WriteError<DE> {
     DirectiveError(DE),
     IO(io::Error),
+    Expression(ExpressionError),
 }
 
 impl<DE> From<io::Error> for WriteError<DE> {
@@ -16,85 +19,390 @@ impl<DE> From<io::Error> for WriteError<DE> {
     }
 }
 
+impl<DE> From<ExpressionError> for WriteError<DE> {
+    fn from(err: ExpressionError) -> Self {
+        WriteError::Expression(err)
+    }
+}
+
+/// The kind of document nodes are rendered as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputMode {
+    Html,
+    Xml, // starts with an XML declaration, every empty element closes itself
+    Text, // text only, elements leave out their tags and interpolated values are not escaped
+}
+
+impl OutputMode {
+    /// The mode for a file extension such as the `xml` of `feed.xml.templar`.
+    pub fn for_extension(extension:&str) -> Option<OutputMode> {
+        match extension {
+            "xml" | "rss" | "atom" | "svg" => Some(OutputMode::Xml),
+            "txt" => Some(OutputMode::Text),
+            "html" | "htm" => Some(OutputMode::Html),
+            _ => None,
+        }
+    }
+}
+
+/// Output is handed to the target writer once this much has been buffered.
+const SINK_CAPACITY: usize = 64 * 1024;
+
+/// Buffers rendered output in memory and hands it to the target writer in large chunks.
+///
+/// A sink without a target keeps everything, for rendering straight into a `String` or `Vec<u8>`.
+///
+/// It also holds the regions filled by `=content_for`, which are written where `=yield name`
+/// left a placeholder once the document is done. Output before the first placeholder is still
+/// handed over as it fills up, everything from the placeholder on waits for the end of the document.
+pub struct Sink<'a> {
+    target: Option<&'a mut dyn Write>,
+    buffer: Vec<u8>,
+    spaces: Vec<u8>, // the widest indentation seen so far
+    mode: OutputMode,
+    regions: BTreeMap<String, String>,
+    placeholders: Vec<(usize, String, usize)>, // buffer offset, region name and indentation
+}
+
+impl<'a> Sink<'a> {
+    pub fn new(target: &'a mut dyn Write) -> Sink<'a> {
+        Sink {
+            target: Some(target),
+            buffer: Vec::with_capacity(SINK_CAPACITY),
+            spaces: Vec::new(),
+            mode: OutputMode::Html,
+            regions: BTreeMap::new(),
+            placeholders: Vec::new(),
+        }
+    }
+
+    pub fn in_memory() -> Sink<'static> {
+        Sink {
+            target: None,
+            buffer: Vec::new(),
+            spaces: Vec::new(),
+            mode: OutputMode::Html,
+            regions: BTreeMap::new(),
+            placeholders: Vec::new(),
+        }
+    }
+
+    pub fn with_mode(mut self, mode: OutputMode) -> Sink<'a> {
+        self.mode = mode;
+        self
+    }
+
+    pub fn mode(&self) -> OutputMode {
+        self.mode
+    }
+
+    /// Everything written to an in memory sink, or whatever has not been flushed yet otherwise.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buffer
+    }
+
+    pub fn write_indent(&mut self, width: usize) {
+        if self.spaces.len() < width {
+            self.spaces.resize(width, b' ');
+        }
+        self.buffer.extend_from_slice(&self.spaces[..width]);
+    }
+
+    pub fn write_str(&mut self, str: &str) {
+        self.buffer.extend_from_slice(str.as_bytes());
+    }
+
+    /// Adds rendered output to the end of a region.
+    pub fn append_region(&mut self, name:&str, content:&str) {
+        self.regions.entry(name.to_string()).or_default().push_str(content);
+    }
+
+    /// Marks where a region is written once the document is done.
+    pub fn placeholder(&mut self, name:&str, indent: usize) {
+        self.placeholders.push((self.buffer.len(), name.to_string(), indent));
+    }
+
+    pub fn has_placeholders(&self) -> bool {
+        !self.placeholders.is_empty()
+    }
+
+    /// Takes over the regions filled while rendering into a separate sink, as `=capture` does.
+    pub fn merge_regions(&mut self, other:&mut Sink) {
+        for (name, content) in std::mem::take(&mut other.regions) {
+            self.append_region(&name, &content);
+        }
+    }
+
+    /// Writes each region where its placeholders are, indenting every line to match.
+    fn fill_placeholders(&mut self) {
+        let placeholders = std::mem::take(&mut self.placeholders);
+        for (offset, name, indent) in placeholders.into_iter().rev() {
+            if let Some(content) = self.regions.get(&name) {
+                let mut text = Vec::with_capacity(content.len());
+                for line in content.split_inclusive('\n') {
+                    if line != "\n" {
+                        text.resize(text.len() + indent, b' ');
+                    }
+                    text.extend_from_slice(line.as_bytes());
+                }
+                self.buffer.splice(offset..offset, text);
+            }
+        }
+    }
+
+    fn drain(&mut self) -> io::Result<()> {
+        let end = self.placeholders.first().map_or(self.buffer.len(), |p| p.0);
+        if end == 0 {
+            return Ok(());
+        }
+        if let Some(ref mut target) = self.target {
+            target.write_all(&self.buffer[..end])?;
+            self.buffer.drain(..end);
+            for placeholder in self.placeholders.iter_mut() {
+                placeholder.0 -= end;
+            }
+        }
+        Ok(())
+    }
+
+    fn drain_if_full(&mut self) -> io::Result<()> {
+        if self.buffer.len() >= SINK_CAPACITY {
+            self.drain()
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'a> Write for Sink<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        self.drain_if_full()?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.drain()?;
+        if let Some(ref mut target) = self.target {
+            target.flush()?;
+        }
+        Ok(())
+    }
+}
 
 pub trait DirectiveHandler {
     type DirectiveError;
-    fn handle<W>(&mut self, context:&TemplateContext, command: &str, children: &[Node], base_indent: usize, indent_size: usize, writer: &mut W) -> Result<(), Self::DirectiveError> where W : Write;
+    fn handle(&mut self, context:&mut TemplateContext, command: &str, children: &[Node], base_indent: usize, indent_size: usize, sink: &mut Sink) -> Result<(), Self::DirectiveError>;
 }
 
-pub fn write_out<W, DH>(nodes:&[Node], context:&TemplateContext, writer:&mut W, base_indent: usize, indent_size: usize, directive_handler:&mut DH) -> Result<(), WriteError<DH::DirectiveError>>
+/// Renders the nodes to a writer, buffering so the writer sees a few large writes.
+pub fn write_out<W, DH>(nodes:&[Node], context:&TemplateContext, writer:&mut W, mode: OutputMode, base_indent: usize, indent_size: usize, directive_handler:&mut DH) -> Result<(), WriteError<DH::DirectiveError>>
     where W : Write, DH: DirectiveHandler {
+    let mut sink = Sink::new(writer).with_mode(mode);
+    render_document(nodes, context, &mut sink, base_indent, indent_size, directive_handler)?;
+    sink.flush()?;
+    Ok(())
+}
+
+pub fn render_to_vec<DH>(nodes:&[Node], context:&TemplateContext, mode: OutputMode, base_indent: usize, indent_size: usize, directive_handler:&mut DH) -> Result<Vec<u8>, WriteError<DH::DirectiveError>>
+    where DH: DirectiveHandler {
+    let mut sink = Sink::in_memory().with_mode(mode);
+    render_document(nodes, context, &mut sink, base_indent, indent_size, directive_handler)?;
+    Ok(sink.into_bytes())
+}
+
+pub fn render_to_string<DH>(nodes:&[Node], context:&TemplateContext, mode: OutputMode, base_indent: usize, indent_size: usize, directive_handler:&mut DH) -> Result<String, WriteError<DH::DirectiveError>>
+    where DH: DirectiveHandler {
+    let bytes = render_to_vec(nodes, context, mode, base_indent, indent_size, directive_handler)?;
+    String::from_utf8(bytes).map_err(|err| WriteError::IO(io::Error::new(io::ErrorKind::InvalidData, err)))
+}
+
+/// Renders a whole document, which for XML means starting with the XML declaration.
+///
+/// Variables the document sets go in a scope of its own, leaving the context it was given as it was.
+fn render_document<DH>(nodes:&[Node], context:&TemplateContext, sink:&mut Sink, base_indent: usize, indent_size: usize, directive_handler:&mut DH) -> Result<(), WriteError<DH::DirectiveError>>
+    where DH: DirectiveHandler {
+    if sink.mode == OutputMode::Xml {
+        sink.write_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    }
+    let mut context = context.with_vars(BTreeMap::new());
+    render(nodes, &mut context, sink, base_indent, indent_size, directive_handler)?;
+    sink.fill_placeholders();
+    Ok(())
+}
+
+/// Renders the nodes into a sink, this is what directive handlers call to render nested nodes.
+pub fn render<DH>(nodes:&[Node], context:&mut TemplateContext, sink:&mut Sink, base_indent: usize, indent_size: usize, directive_handler:&mut DH) -> Result<(), WriteError<DH::DirectiveError>>
+    where DH: DirectiveHandler {
+    if sink.mode == OutputMode::Text {
+        return render_text(nodes, context, sink, directive_handler);
+    }
+
     for node in nodes {
         if node.should_indent() {
-            for _ in 0..base_indent {
-                writer.write(b" ")?;
-            }
+            sink.write_indent(base_indent);
         }
 
         match node {
-            &Node::Doctype(ref doctype) => {
-                let out = format!("<!DOCTYPE {}>\n", doctype);
-                writer.write(out.as_bytes())?;
-                writer.write(b"\n")?;
+            Node::Doctype(doctype) => {
+                sink.write_str("<!DOCTYPE ");
+                sink.write_str(doctype);
+                sink.write_str(">\n\n");
             }
-            &Node::Directive { ref command, ref children } => {
-                // println!("handle directive -> {:?} children {:?}", command, children);
-                directive_handler.handle(context, command, children, base_indent, indent_size, writer).map_err(WriteError::DirectiveError)?;
+            Node::Directive { command, children } => {
+                directive_handler.handle(context, command, children, base_indent, indent_size, sink).map_err(WriteError::DirectiveError)?;
             }
-            &Node::Text(ref text) => {
-//                let out = escape_html(text).expect("escaped text");
-//                writer.write(out.as_bytes())?;
-                writer.write(text.as_bytes())?;
+            Node::Text(text) => {
+                let text = interpolate(text, context, sink.mode)?;
+                // values such as captured markup can span lines, which are indented to match
+                for (idx, line) in text.split('\n').enumerate() {
+                    if idx > 0 {
+                        sink.write_str("\n");
+                        if indent_size > 0 && !line.is_empty() {
+                            sink.write_indent(base_indent);
+                        }
+                    }
+                    sink.write_str(line);
+                }
                 if indent_size > 0 {
-                    writer.write(b"\n")?;
+                    sink.write_str("\n");
                 }
             },
-            &Node::RawText(ref raw_text) => {
-                writer.write(raw_text.as_bytes())?;
-                writer.write(b"\n")?;
+            Node::RawText(raw_text) => {
+                sink.write_str(raw_text);
+                sink.write_str("\n");
             },
-            &Node::Element(ref element) => {
-                let destroy_whitespace = element.name == "a";
-                let seperate_close_tag = element.children.len() > 0 || element.name == "script" || element.name == "a";
-                let trailing_slash : &str = if !seperate_close_tag { " /" } else { "" };
-
-//                println!("ele -> {:?} Close tag -> {:?} trailing slash -> {:?}", element, seperate_close_tag, trailing_slash);
-
-                let open_tag : String = if element.attributes.is_empty() {
-                    format!("<{}{}>", element.name, trailing_slash)
-                } else {
-                    let attributes : Vec<String> = element.attributes.iter().map(|&(ref k, ref v)|
-                        format!("{}=\"{}\"", k, escape_default(v))
-                    ).collect();
-                    format!("<{} {}{}>", element.name, attributes.join(" "), trailing_slash)
-                };
-                writer.write(open_tag.as_bytes())?;
+            &Node::Comment(_) => {},
+            Node::Element(element) => {
+                let html = sink.mode == OutputMode::Html;
+                let destroy_whitespace = html && element.name == "a";
+                let has_content = element.children.iter().any(|c| !matches!(*c, Node::Comment(_)));
+                let seperate_close_tag = has_content || (html && (element.name == "script" || element.name == "a"));
+
+                sink.write_str("<");
+                sink.write_str(&element.name);
+                for (k, v) in element.attributes.iter() {
+                    sink.write_str(" ");
+                    sink.write_str(k);
+                    sink.write_str("=\"");
+                    // interpolated values are already escaped, a quote anywhere else would end the value
+                    sink.write_str(&interpolate(v, context, sink.mode)?.replace('"', "&quot;"));
+                    sink.write_str("\"");
+                }
+                sink.write_str(if seperate_close_tag { ">" } else { " />" });
+
                 if indent_size > 0 && !destroy_whitespace {
-                    writer.write(b"\n")?;
+                    sink.write_str("\n");
                 }
                 if seperate_close_tag {
                     if destroy_whitespace {
-                        write_out(element.children.as_slice(), context, writer, 0, 0, directive_handler)?;
+                        render(element.children.as_slice(), context, sink, 0, 0, directive_handler)?;
                     } else {
-                        write_out(element.children.as_slice(), context, writer, base_indent + indent_size, indent_size, directive_handler)?;
+                        render(element.children.as_slice(), context, sink, base_indent + indent_size, indent_size, directive_handler)?;
                     }
 
-                    let closing_tag : String = format!("</{}>", element.name);
                     if !destroy_whitespace {
-                        for _ in 0..base_indent {
-                            writer.write(b" ")?;
-                        }
+                        sink.write_indent(base_indent);
                     }
-                    writer.write(closing_tag.as_bytes())?;
+                    sink.write_str("</");
+                    sink.write_str(&element.name);
+                    sink.write_str(">");
                     if indent_size > 0 {
-                        writer.write(b"\n")?;
+                        sink.write_str("\n");
                     }
 
                 }
 
             },
         }
+
+        sink.drain_if_full()?;
+    }
+
+    Ok(())
+}
+
+/// Text mode writes each text line as it is with no indentation, elements only contribute their content.
+fn render_text<DH>(nodes:&[Node], context:&mut TemplateContext, sink:&mut Sink, directive_handler:&mut DH) -> Result<(), WriteError<DH::DirectiveError>>
+    where DH: DirectiveHandler {
+    for node in nodes {
+        match node {
+            Node::Directive { command, children } => {
+                directive_handler.handle(context, command, children, 0, 0, sink).map_err(WriteError::DirectiveError)?;
+            },
+            Node::Text(text) => {
+                sink.write_str(&interpolate(text, context, OutputMode::Text)?);
+                sink.write_str("\n");
+            },
+            Node::RawText(raw_text) => {
+                sink.write_str(raw_text);
+                sink.write_str("\n");
+            },
+            Node::Element(element) => render_text(&element.children, context, sink, directive_handler)?,
+            &Node::Doctype(_) | &Node::Comment(_) => {},
+        }
+
+        sink.drain_if_full()?;
     }
 
     Ok(())
 }
+
+/// Replaces `#{expression}` and `!{expression}` with the value of the expression, `#{...}` is
+/// escaped for HTML or XML as the mode needs, text output isn't escaped, and `!{...}` never is.
+///
+/// Every text and attribute value is interpolated, so templates written before interpolation
+/// existed that contain a literal `#{` or `!{` need it written as `\#{` or `\!{` instead.
+pub fn interpolate<'a>(text:&'a str, context:&TemplateContext, mode: OutputMode) -> Result<Cow<'a, str>, ExpressionError> {
+    if !text.as_bytes().contains(&b'{') || (!text.contains("#{") && !text.contains("!{")) {
+        return Ok(Cow::Borrowed(text));
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(['#', '!']) {
+        let (before, marker) = rest.split_at(start);
+        if before.ends_with('\\') && marker[1..].starts_with('{') {
+            // an escaped marker is written without its backslash and not evaluated
+            out.push_str(&before[..before.len() - 1]);
+            out.push_str(&marker[..2]);
+            rest = &marker[2..];
+            continue;
+        }
+        out.push_str(before);
+
+        let end = if marker[1..].starts_with('{') { closing_brace(marker) } else { None };
+        match end {
+            Some(end) => {
+                let value = context.evaluate(&marker[2..end])?.to_string();
+                match mode {
+                    OutputMode::Html if marker.starts_with('#') => out.push_str(&escape_html(&value).expect("escaped value")),
+                    OutputMode::Xml if marker.starts_with('#') => out.push_str(&escape_xml(&value)),
+                    _ => out.push_str(&value),
+                }
+                rest = &marker[end + 1..];
+            },
+            None => {
+                out.push_str(&marker[..1]);
+                rest = &marker[1..];
+            },
+        }
+    }
+    out.push_str(rest);
+    Ok(Cow::Owned(out))
+}
+
+/// The closing brace of an interpolation, skipping any inside quoted strings.
+fn closing_brace(marker:&str) -> Option<usize> {
+    let mut quote = None;
+    for (idx, c) in marker.char_indices().skip(2) {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {},
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '}' => return Some(idx),
+            None => {},
+        }
+    }
+    None
+}
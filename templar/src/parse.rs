@@ -1,209 +1,210 @@
-use nom::*; // {digit, space, alphanumeric}
-use std::str;
 use std::cmp::max;
+use std::fmt;
 
 use contains;
 
-fn always<T>(_:T) -> bool {
-    true
-}
+use super::{Node, Element, element, is_void_element};
 
-fn is_identifier(c:char) -> bool {
+pub(crate) fn is_identifier(c:char) -> bool {
     c.is_alphanumeric() || c == '-' || c == '_'
 }
 
-fn is_spacer(c:char) -> bool {
-    c.is_whitespace()
-}
-
-fn noneify_blank_string(str: &str) -> Option<String> {
+fn noneify_blank_string(str: &str) -> Option<&str> {
     if str.is_empty() {
         None
     }  else {
-        Some(str.into())
-    }
-}
-
-named!(identifier<&str, &str>,
-    take_while1!(is_identifier)
-);
-
-named!(element_id<&str, &str>,
-    do_parse!(
-        tag!("#") >>
-        id : identifier >>
-        (id)
-    )
-);
-
-named!(rest<&str, &str>,
-    take_while!(always)
-);
-
-named!(element_class<&str, &str>,
-    do_parse!(
-        tag!(".") >>
-        class_name : identifier >>
-        (class_name)
-    )
-);
-
-named!(quoted_value<&str, &str>,
-    delimited!(
-        tag!("\""),
-        take_until!("\""),
-        tag!("\"")
-    )
-);
-
-named!(attribute_content<&str, &str>,
-    take_till!(is_spacer)
-);
-
-named!(key_value_pair<&str, (String, String)>,
-    do_parse!(
-        k: identifier >>
-        tag!("=") >>
-        v: alt_complete!( quoted_value | attribute_content ) >>
-        (k.into(), v.into())
-    )
-);
-
-named!(text_line<&str, LineContent>,
-    do_parse!(
-        tag!("|") >>
-        rr: map!(rest, |s| s.trim() ) >>
-        ( LineContent::Text(rr.to_string()) )
-    )
-);
-
-named!(javascript_text_line<&str, LineContent>,
-    do_parse!(
-        rr: rest >>
-        ( LineContent::Text( rr.to_string()) )
-    )
-);
-
-named!(directive_line<&str, LineContent>,
-    do_parse!(
-        tag!("=") >>
-        rr : rest >>
-        ( LineContent::Directive(rr.trim().to_string()) )
-    )
-);
-
-named!(comment_line<&str, LineContent>,
-    do_parse!(
-        tag!("/") >>
-        rr : rest >>
-        ( LineContent::Comment(rr.trim().to_string()))
-    )
-);
-
-named!(doctype_line<&str, LineContent>,
-    do_parse!(
-        tag!("doctype") >>
-        space >>
-        rr: rest >>
-        ( LineContent::Doctype(rr.trim().to_string()) )
-    )
-);
-
-named!(javascript_line<&str, LineContent>,
-    do_parse!(
-        tag!(":javascript") >>
-        rr : rest >>
-        ( LineContent::Javascript )
-    )
-);
-
-named!(css_line<&str, LineContent>,
-    do_parse!(
-        tag!(":css") >>
-        rr : rest >>
-        ( LineContent::StyleSheet )
-    )
-);
-
-named!(tag_element_line<&str, LineContent>,
-    do_parse!(
-        tag: identifier >>
-        class_ids: many0!(
-            alt_complete!(
-                map!(element_class, |s| ClassId::Class(s.to_string())) |
-                map!(element_id, |s| ClassId::Id(s.to_string()))
-            )
-        ) >>
-        kvps: many0!(ws!(complete!(key_value_pair))) >>
-        rr : rest >>
-        ( LineContent::Element(HtmlElement {
-                tag: Some(tag.to_string()),
-                classes_ids: class_ids,
-                attributes: kvps,
-                inner_text: noneify_blank_string(rr.trim()),
-          })
-        )
-    )
-);
-
-named!(class_id_only_line<&str, LineContent>,
-    do_parse!(
-        class_ids: many1!(
-            alt_complete!(
-                map!(element_class, |s| ClassId::Class(s.to_string())) |
-                map!(element_id, |s| ClassId::Id(s.to_string()))
-            )
-        ) >>
-        kvps: many0!(ws!(complete!(key_value_pair))) >>
-        rr : rest >>
-        ( LineContent::Element(HtmlElement {
-                tag: None,
-                classes_ids: class_ids,
-                attributes: kvps,
-                inner_text: noneify_blank_string(rr.trim()),
-          })
-        )
-    )
-);
-
-named!(line_p<&str, LineContent>,
-    alt_complete!(doctype_line | comment_line | javascript_line | css_line | tag_element_line | class_id_only_line | directive_line | text_line)
-);
+        Some(str)
+    }
+}
+
+/// Splits a leading identifier off the input.
+pub(crate) fn identifier(input: &str) -> Option<(&str, &str)> {
+    let end = input.find(|c| !is_identifier(c)).unwrap_or(input.len());
+    if end == 0 {
+        None
+    } else {
+        Some(input.split_at(end))
+    }
+}
+
+/// Splits a leading `.class` or `#id` off the input.
+fn class_or_id<'a>(input: &'a str) -> Option<(ClassId<'a>, &'a str)> {
+    let is_id = if input.starts_with('#') {
+        true
+    } else if input.starts_with('.') {
+        false
+    } else {
+        return None;
+    };
+
+    identifier(&input[1..]).map(|(name, rest)| {
+        if is_id {
+            (ClassId::Id(name), rest)
+        } else {
+            (ClassId::Class(name), rest)
+        }
+    })
+}
+
+/// Splits a leading `key=value` or `key="quoted value"` off the input.
+fn key_value_pair(input: &str) -> Option<((&str, &str), &str)> {
+    let (key, rest) = identifier(input)?;
+    if !rest.starts_with('=') {
+        return None;
+    }
+    let rest = &rest[1..];
+
+    if let Some(quoted) = rest.strip_prefix('"') {
+        if let Some(end) = quoted.find('"') {
+            return Some(((key, &quoted[..end]), &quoted[end + 1..]));
+        }
+    }
+
+    // unquoted, or a quote which is never closed
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    Some(((key, &rest[..end]), &rest[end..]))
+}
+
+/// Splits the directive or keyword `tag` off the input.
+fn keyword<'a>(input: &'a str, tag: &str) -> Option<&'a str> {
+    input.strip_prefix(tag)
+}
 
 #[derive(Debug)]
-enum ClassId {
-    Id(String),
-    Class(String),
+enum ClassId<'a> {
+    Id(&'a str),
+    Class(&'a str),
 }
 
 #[derive(Debug)]
-struct HtmlElement {
-    tag: Option<String>,
-    classes_ids: Vec<ClassId>,
-    attributes: Vec<(String, String)>,
-    inner_text: Option<String>,
+struct HtmlElement<'a> {
+    tag: Option<&'a str>,
+    classes_ids: Vec<ClassId<'a>>,
+    attributes: Vec<(&'a str, &'a str)>,
+    inner_text: Option<&'a str>,
 }
 
 #[derive(Debug)]
-enum LineContent {
-    Comment(String),
+enum LineContent<'a> {
+    Comment(&'a str),
     Javascript,
-    Doctype(String),
-    Element(HtmlElement),
-    Directive(String),
-    Text(String),
+    Doctype(&'a str),
+    Element(HtmlElement<'a>),
+    Directive(&'a str),
+    Text(&'a str),
     StyleSheet,
+    Code(&'a str), // the language, may be empty
+}
+
+/// `tag.class#id key=value inner text`, where either the tag or the classes and ids may be left out.
+fn element_line<'a>(input: &'a str) -> Option<HtmlElement<'a>> {
+    let (tag, mut rest) = match identifier(input) {
+        Some((tag, rest)) => (Some(tag), rest),
+        None => (None, input),
+    };
+
+    let mut classes_ids = Vec::new();
+    while let Some((class_id, after)) = class_or_id(rest) {
+        classes_ids.push(class_id);
+        rest = after;
+    }
+
+    if tag.is_none() && classes_ids.is_empty() {
+        return None;
+    }
+
+    let mut attributes = Vec::new();
+    while let Some((pair, after)) = key_value_pair(rest.trim_start()) {
+        attributes.push(pair);
+        rest = after;
+    }
+
+    Some(HtmlElement {
+        tag,
+        classes_ids,
+        attributes,
+        inner_text: noneify_blank_string(rest.trim()),
+    })
+}
+
+/// Works out what a line holds, given the line without its indentation.
+fn line_content<'a>(input: &'a str) -> Result<LineContent<'a>, String> {
+    if let Some(rest) = keyword(input, "doctype") {
+        if rest.starts_with(' ') || rest.starts_with('\t') {
+            return Ok(LineContent::Doctype(rest.trim()));
+        }
+    }
+    if let Some(rest) = keyword(input, "/") {
+        return Ok(LineContent::Comment(rest.trim()));
+    }
+    if keyword(input, ":javascript").is_some() {
+        return Ok(LineContent::Javascript);
+    }
+    if keyword(input, ":css").is_some() {
+        return Ok(LineContent::StyleSheet);
+    }
+    if let Some(rest) = keyword(input, ":code") {
+        if rest.is_empty() || rest.starts_with(' ') || rest.starts_with('\t') {
+            return Ok(LineContent::Code(rest.trim()));
+        }
+    }
+    if let Some(element) = element_line(input) {
+        return Ok(LineContent::Element(element));
+    }
+    if let Some(rest) = keyword(input, "=") {
+        return Ok(LineContent::Directive(rest.trim()));
+    }
+    if let Some(rest) = keyword(input, "|") {
+        return Ok(LineContent::Text(rest.trim()));
+    }
+
+    let first = input.chars().next().expect("a non blank line");
+    Err(format!("a line cannot start with {:?}", first))
 }
 
+/// Byte offset of the first non whitespace character, `None` for blank lines.
 fn indentation(str: &str) -> Option<usize> {
-    str.chars().position(|c| !c.is_whitespace())
+    str.find(|c: char| !c.is_whitespace())
 }
 
-use super::{Node, Element, element};
+/// Takes the indentation all lines of a `:code` block share off each of them.
+fn dedent_code(node: &mut Node) {
+    if let &mut Node::Directive { ref command, ref mut children } = node {
+        if command != "code" && !command.starts_with("code ") {
+            return;
+        }
+        let common = children.iter().filter_map(|c| match c {
+            Node::RawText(text) => indentation(text),
+            _ => None,
+        }).min().unwrap_or(0);
+        for child in children.iter_mut() {
+            if let &mut Node::RawText(ref mut text) = child {
+                if let Some(dedented) = text.get(common..).map(str::to_string) {
+                    *text = dedented;
+                }
+            }
+        }
+    }
+}
+
+fn void_name(node: &Node) -> Option<String> {
+    match node {
+        Node::Element(ele) if is_void_element(&ele.name) => Some(ele.name.clone()),
+        _ => None,
+    }
+}
 
 pub type ParseResult = Result<Vec<Node>, ParseError>;
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct RecoveredParse {
+    pub nodes: Vec<Node>, // best effort, lines with errors are left out
+    pub errors: Vec<ParseError>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
 pub struct ParseError {
     pub line_number: usize,
     pub context: Vec<String>, // last few lines
@@ -212,26 +213,106 @@ pub struct ParseError {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
 pub enum ErrorReason {
     MisplacedDocType,
     MultipleIds,
     IllegalNesting(String),
+    MixedIndentation, // tabs and spaces in the same file
+    UnmatchedDedent(usize), // dedented to a level that no open parent is at
+    NestedInVoid(String), // indented under a node that cannot have children
     Parse(String),
 }
 
+/// The line number and reason, followed by the lines leading up to the error.
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line_number + 1, self.reason)?;
+        let first_line = (self.line_number + 1).saturating_sub(self.context.len());
+        for (idx, line) in self.context.iter().enumerate() {
+            let marker = if first_line + idx == self.line_number { ">" } else { " " };
+            write!(f, "\n{}{:>4} | {}", marker, first_line + idx + 1, line)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ErrorReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ErrorReason::MisplacedDocType => write!(f, "doctype must come before anything else"),
+            &ErrorReason::MultipleIds => write!(f, "an element can only have one id"),
+            ErrorReason::IllegalNesting(reason) => write!(f, "illegal nesting: {}", reason),
+            &ErrorReason::MixedIndentation => write!(f, "tabs and spaces are mixed in the indentation"),
+            &ErrorReason::UnmatchedDedent(indent) => write!(f, "dedented to an indentation of {} which no parent has", indent),
+            ErrorReason::NestedInVoid(name) => write!(f, "{} cannot have children", name),
+            ErrorReason::Parse(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// Steps taken by the parser, reported to `ParseOptions::trace` when diagnosing a template.
+#[derive(Debug)]
+pub enum TraceEvent {
+    Line { line_number: usize, indent: usize, content: String },
+    Skip { line_number: usize },
+    Open { line_number: usize, indent: usize, node: String },
+    Close { line_number: usize, indent: usize, node: String, into_root: bool },
+}
+
+/// Called with each step the parser takes.
+pub type Trace = Box<dyn FnMut(&TraceEvent)>;
+
+pub struct ParseOptions {
+    pub recover: bool, // carry on past errors, see `parse_recovering`
+    pub void_elements: bool, // children of HTML void elements such as img are errors, off for XML
+    pub trace: Option<Trace>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions {
+            recover: false,
+            void_elements: true,
+            trace: None,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Options which print every parser step to stderr.
+    pub fn trace_to_stderr() -> ParseOptions {
+        ParseOptions {
+            trace: Some(Box::new(|event| eprintln!("{:?}", event))),
+            ..ParseOptions::default()
+        }
+    }
+}
+
+fn describe(node: &Node) -> String {
+    match node {
+        Node::Element(ele) => ele.name.clone(),
+        Node::Directive { command, .. } => format!("={}", command),
+        _ => format!("{:?}", node),
+    }
+}
+
 #[derive(Eq, PartialEq, Debug, Clone, Copy)]
 enum ParseMode {
     Normal,
     InlineJavascript,
+    Code, // keeps indentation and blank lines, unlike javascript
 }
 
 fn element_for(html_element: HtmlElement) -> Result<Element, ErrorReason> {
-    let name = html_element.tag.unwrap_or_else(|| "div".into());
+    let name = html_element.tag.unwrap_or("div").to_string();
 
-    let mut attributes = html_element.attributes;
+    let mut attributes : Vec<(String, String)> = html_element.attributes.iter()
+        .map(|&(k, v)| (k.to_string(), v.to_string()))
+        .collect();
 
-    let mut id : Option<String> = None;
-    let mut classes : Vec<String> = Vec::new();
+    let mut id : Option<&str> = None;
+    let mut classes : Vec<&str> = Vec::new();
 
     for class_id in html_element.classes_ids {
         match class_id {
@@ -249,7 +330,7 @@ fn element_for(html_element: HtmlElement) -> Result<Element, ErrorReason> {
     }
 
     if let Some(id) = id {
-        attributes.push(("id".into(), id));
+        attributes.push(("id".into(), id.to_string()));
     }
     if !classes.is_empty() {
         attributes.push(("class".into(), classes.join(" ")));
@@ -258,7 +339,7 @@ fn element_for(html_element: HtmlElement) -> Result<Element, ErrorReason> {
     let mut children = Vec::new();
 
     if let Some(text) = html_element.inner_text {
-        children.push(Node::Text(text));
+        children.push(Node::Text(text.to_string()));
     }
 
     Ok(Element {
@@ -270,183 +351,415 @@ fn element_for(html_element: HtmlElement) -> Result<Element, ErrorReason> {
 
 
 pub fn parse(content:&str) -> ParseResult {
+    let mut parsed = parse_with(content, ParseOptions::default());
+    if parsed.errors.is_empty() {
+        Ok(parsed.nodes)
+    } else {
+        Err(parsed.errors.remove(0))
+    }
+}
+
+/// Parses the whole template even when lines fail, skipping the children of each bad line and
+/// collecting every error along with the nodes that could be parsed.
+pub fn parse_recovering(content:&str) -> RecoveredParse {
+    parse_with(content, ParseOptions { recover: true, ..ParseOptions::default() })
+}
+
+fn error_at(lines: &[&str], line_number: usize, character: Option<usize>, reason: ErrorReason) -> ParseError {
+    let start_line = max((line_number as i64) - 5, 0) as usize;
+    let end_line = line_number + 1;
+    ParseError {
+        line_number,
+        context: lines[start_line..end_line].iter().map(|l| l.to_string()).collect(),
+        character: character.map(|c| c as u64),
+        reason,
+    }
+}
+
+/// When parsing carries on past errors the child is left out of the tree, which the reason says.
+fn illegal_nesting(parent: &Node, child: &Node, dropped: bool) -> ErrorReason {
+    let note = if dropped { ", the child is left out" } else { "" };
+    ErrorReason::IllegalNesting(format!("parent -> {:?} child -> {:?}{}", parent, child, note))
+}
+
+pub fn parse_with(content:&str, mut options: ParseOptions) -> RecoveredParse {
+    let recover = options.recover;
     let mut out_nodes: Vec<Node> = Vec::new();
-    let mut out_stack: Vec<(Node, usize)> = Vec::new();
+    let mut out_stack: Vec<(Node, usize, usize)> = Vec::new(); // open nodes, their indentation and line
+    let mut errors: Vec<ParseError> = Vec::new();
 
     let mut mode = ParseMode::Normal;
 
-    let lines : Vec<String> = content.lines().map(|s|s.to_string()).collect();
+    let lines : Vec<&str> = content.lines().collect();
+
+    // indentation of the last line that failed, its children are skipped
+    let mut skip_children_of : Option<usize> = None;
+    // the whitespace character the file is indented with, fixed by the first indented line
+    let mut indent_char : Option<char> = None;
+    // indentation and kind of the last text or doctype line, which cannot have children
+    let mut last_void_line : Option<(usize, &'static str)> = None;
+    // whether the open :code block has a line yet, and the blank lines since its last line,
+    // which are only kept when more code follows them
+    let mut code_started = false;
+    let mut blank_code_lines = 0;
+
+    macro_rules! trace {
+        ($event:expr) => {
+            if let Some(ref mut trace) = options.trace {
+                trace(&$event);
+            }
+        }
+    }
 
-    let produce_context = |line_number: usize| -> Vec<String> {
-        let start_line = max((line_number as i64) - 5, 0) as usize;
-        let end_line = line_number + 1;
-        let ter = &lines[start_line..end_line];
-        ter.iter().cloned().collect()
-    };
+    // records an error for the current line, then bails or moves on to the next sibling line
+    macro_rules! fail {
+        ($line_idx:expr, $indent:expr, $reason:expr) => {
+            fail!($line_idx, $indent, None, $reason)
+        };
+        ($line_idx:expr, $indent:expr, $character:expr, $reason:expr) => {{
+            errors.push(error_at(&lines, $line_idx, $character, $reason));
+            if !recover {
+                return RecoveredParse { nodes: out_nodes, errors };
+            }
+            skip_children_of = Some($indent);
+            continue;
+        }}
+    }
 
     // for each line in the current file,
-    for (line_idx, line) in lines.iter().enumerate() {
-        // println!("line {}\n{}", line_idx, line);
+    for (line_idx, &line) in lines.iter().enumerate() {
         // indentation and slicing first
-//        println!("-> {}", line);
         if let Some(indent) = indentation(line) {
-            let (_, rest) = line.split_at(indent);
+            if contains(skip_children_of, |&n| indent > n) {
+                trace!(TraceEvent::Skip { line_number: line_idx });
+                continue;
+            }
+            skip_children_of = None;
+
+            let (leading, rest) = line.split_at(indent);
 
-//            println!("!indent is {:?}", indent);
+            // lines inside a :javascript, :css or :code block are raw text, their whitespace is their own
+            let in_raw_block = mode != ParseMode::Normal && contains(out_stack.last(), |&&(_, n, _)| n < indent);
+
+            if !in_raw_block {
+                let mixed = leading.chars().any(|c| *indent_char.get_or_insert(c) != c);
+                if mixed {
+                    fail!(line_idx, indent, ErrorReason::MixedIndentation);
+                }
+
+                if let Some((void_indent, kind)) = last_void_line {
+                    if indent > void_indent {
+                        fail!(line_idx, indent, ErrorReason::NestedInVoid(kind.to_string()));
+                    }
+                }
+            }
+            last_void_line = None;
+
+            let mut dedented_to : Option<usize> = None;
 
             // while the next element on the stack is indented more than the current line,
-            while contains(out_stack.last(), |&&(_, n)| n >= indent ) {
-                let (node, _) = out_stack.pop().expect("the top element");
-
-                if let Some(&mut (ref mut next_down, _)) = out_stack.last_mut() {
-//                    println!("! push top element {:?} to next down {:?}", ele.name, next_down.name);
-                    if !next_down.append_child(node.clone()) {
-                        return Err(ParseError {
-                            line_number: line_idx,
-                            context: produce_context(line_idx),
-                            character: None,
-                            reason: ErrorReason::IllegalNesting(format!("parent -> {:?} child -> {:?}", next_down, node)),
-                        });
+            while contains(out_stack.last(), |&&(_, n, _)| n >= indent ) {
+                let (mut node, node_indent, node_line) = out_stack.pop().expect("the top element");
+                dedent_code(&mut node);
+                dedented_to = Some(node_indent);
+                trace!(TraceEvent::Close { line_number: line_idx, indent: node_indent, node: describe(&node), into_root: out_stack.is_empty() });
+
+                if let Some(&mut (ref mut next_down, _, _)) = out_stack.last_mut() {
+                    if next_down.supports_children() {
+                        next_down.append_child(node);
+                    } else {
+                        errors.push(error_at(&lines, node_line, None, illegal_nesting(next_down, &node, recover)));
+                        if !recover {
+                            return RecoveredParse { nodes: out_nodes, errors };
+                        }
                     }
                 } else {
-//                    println!("! push top element {:?} to out", ele.name);
                     out_nodes.push(node);
                 }
                 mode = ParseMode::Normal
             }
 
-            let line_content_result = match mode {
-                ParseMode::InlineJavascript => javascript_text_line(rest),
-                ParseMode::Normal => line_p(rest)
-            };
+            // the line must line up with the last node it closed, otherwise it sits between levels
+            if let Some(node_indent) = dedented_to {
+                if node_indent != indent {
+                    fail!(line_idx, indent, ErrorReason::UnmatchedDedent(indent));
+                }
+            }
 
-            match line_content_result {
-                IResult::Done(_, line_content) => {
-                   println!("Done-> {}", format!("{:?}",line_content));
-
-                    match (mode, line_content) {
-                        (ParseMode::InlineJavascript, LineContent::Text(string)) => {
-                            let &mut (ref mut next_down, _) = out_stack.last_mut().expect("a javascript node");
-                            let node = Node::RawText(string);
-                            if !next_down.append_child(node.clone()) {
-                                return Err(ParseError {
-                                    line_number: line_idx,
-                                    context: produce_context(line_idx),
-                                    character: None,
-                                    reason: ErrorReason::IllegalNesting(format!("parent -> {:?} child -> {:?}", next_down, node)),
-                                });
-                            }
-                        },
-                        (ParseMode::InlineJavascript, _) => {
-                            panic!("uhh")
+            if mode == ParseMode::InlineJavascript {
+                trace!(TraceEvent::Line { line_number: line_idx, indent, content: format!("{:?}", rest) });
+                let &mut (ref mut script, _, _) = out_stack.last_mut().expect("a javascript node");
+                script.append_child(Node::RawText(rest.to_string()));
+                continue;
+            }
+
+            if mode == ParseMode::Code {
+                trace!(TraceEvent::Line { line_number: line_idx, indent, content: format!("{:?}", rest) });
+                let &mut (ref mut code, _, _) = out_stack.last_mut().expect("a code node");
+                for _ in 0..blank_code_lines {
+                    code.append_child(Node::RawText(String::new()));
+                }
+                blank_code_lines = 0;
+                code_started = true;
+                // the block's common indentation is taken off when it closes
+                code.append_child(Node::RawText(line.to_string()));
+                continue;
+            }
+
+            if let Some(name) = out_stack.last().and_then(|(node, _, _)| void_name(node)).filter(|_| options.void_elements) {
+                fail!(line_idx, indent, ErrorReason::NestedInVoid(name));
+            }
+
+            let content = match line_content(rest) {
+                Ok(content) => content,
+                Err(message) => fail!(line_idx, indent, Some(indent), ErrorReason::Parse(message)),
+            };
+            trace!(TraceEvent::Line { line_number: line_idx, indent, content: format!("{:?}", content) });
+
+            match content {
+                LineContent::Comment(string) => {
+                    let node = Node::Comment(string.to_string());
+                    if let Some(&mut (ref mut next_down, _, _)) = out_stack.last_mut() {
+                        next_down.append_child(node);
+                    } else {
+                        out_nodes.push(node);
+                    }
+                },
+                LineContent::Javascript => {
+                    let mut ele = element("script", vec![("type", "text/javascript")]);
+                    ele.children.push(Node::RawText("\n".into()));
+                    mode = ParseMode::InlineJavascript;
+                    trace!(TraceEvent::Open { line_number: line_idx, indent, node: ele.name.clone() });
+                    out_stack.push((Node::Element(ele), indent, line_idx));
+                },
+                LineContent::StyleSheet => {
+                    let mut ele = element("style", vec![]);
+                    ele.children.push(Node::RawText("\n".into()));
+                    mode = ParseMode::InlineJavascript;
+                    trace!(TraceEvent::Open { line_number: line_idx, indent, node: ele.name.clone() });
+                    out_stack.push((Node::Element(ele), indent, line_idx));
+                },
+                LineContent::Code(language) => {
+                    let command = if language.is_empty() { "code".to_string() } else { format!("code {}", language) };
+                    let node = Node::Directive { command, children: Vec::new() };
+                    mode = ParseMode::Code;
+                    code_started = false;
+                    blank_code_lines = 0;
+                    trace!(TraceEvent::Open { line_number: line_idx, indent, node: describe(&node) });
+                    out_stack.push((node, indent, line_idx));
+                },
+                LineContent::Doctype(string) => {
+                    if !out_stack.is_empty() {
+                        fail!(line_idx, indent, ErrorReason::MisplacedDocType);
+                    }
+                    out_nodes.push(Node::Doctype(string.to_string()));
+                    last_void_line = Some((indent, "doctype"));
+                },
+                LineContent::Element(ele) => {
+                    match element_for(ele) {
+                        Ok(e) => {
+                            trace!(TraceEvent::Open { line_number: line_idx, indent, node: e.name.clone() });
+                            out_stack.push((Node::Element(e), indent, line_idx));
                         },
-                        (ParseMode::Normal, content) => {
-                            match content {
-                                LineContent::Comment(_) => {
-                                    // ignore
-                                },
-                                LineContent::Javascript => {
-                                    let mut ele = element("script", vec![("type", "text/javascript")]);
-                                    ele.children.push(Node::RawText("\n".into()));
-                                    mode = ParseMode::InlineJavascript;
-                                    out_stack.push((Node::Element(ele), indent));
-                                },
-                                LineContent::StyleSheet => {
-                                    let mut ele = element("style", vec![]);
-                                    ele.children.push(Node::RawText("\n".into()));
-                                    mode = ParseMode::InlineJavascript;
-                                    out_stack.push((Node::Element(ele), indent));
-                                },
-                                LineContent::Doctype(string) => {
-                                    if !out_stack.is_empty() {
-                                        return Err(ParseError {
-                                            line_number: line_idx,
-                                            context: produce_context(line_idx),
-                                            character: None,
-                                            reason: ErrorReason::MisplacedDocType,
-                                        });
-                                    }
-//                                    println!("!doctype to out");
-                                    out_nodes.push(Node::Doctype(string));
-                                },
-                                LineContent::Element(ele) => {
-                                    match element_for(ele) {
-                                        Ok(e) => {
-//                                            println!("!{}", format!("pushing element {:?}", e.name));
-                                            out_stack.push((Node::Element(e), indent));
-                                        },
-                                        Err(reason) => {
-                                            return Err(ParseError {
-                                                line_number: line_idx,
-                                                context: produce_context(line_idx),
-                                                character: None,
-                                                reason,
-                                            });
-                                        },
-                                    }
-                                },
-                                LineContent::Directive(string) => {
-                                    let node = Node::Directive { command: string, children: Vec::new() };
-                                    out_stack.push((node, indent));
-                                },
-                                LineContent::Text(string) => {
-                                    let node = Node::Text(string);
-                                    if let Some(&mut (ref mut next_down, _)) = out_stack.last_mut() {
-//                                        println!("!push text to parent {:?}", next_down.name);
-                                        if !next_down.append_child(node.clone()) {
-                                            return Err(ParseError {
-                                                line_number: line_idx,
-                                                context: produce_context(line_idx),
-                                                character: None,
-                                                reason: ErrorReason::IllegalNesting(format!("parent -> {:?} child -> {:?}", next_down, node)),
-                                            });
-                                        }
-                                    } else {
-//                                        println!("!push text to root");
-                                        out_nodes.push(node);
-                                    }
-                                },
-                            }
+                        Err(reason) => {
+                            fail!(line_idx, indent, reason);
                         },
                     }
                 },
-                IResult::Error(err) => {
-                    return Err(ParseError {
-                        line_number: line_idx,
-                        context: produce_context(line_idx),
-                        character: None,
-                        reason:ErrorReason::Parse(format!("{:?}", err)),
-                    });
+                LineContent::Directive(string) => {
+                    let node = Node::Directive { command: string.to_string(), children: Vec::new() };
+                    trace!(TraceEvent::Open { line_number: line_idx, indent, node: describe(&node) });
+                    out_stack.push((node, indent, line_idx));
                 },
-                IResult::Incomplete(needed) => {
-                    return Err(ParseError {
-                        line_number: line_idx,
-                        context: produce_context(line_idx),
-                        character: None,
-                        reason:ErrorReason::Parse(format!("{:?}", needed)),
-                    });
+                LineContent::Text(string) => {
+                    last_void_line = Some((indent, "text"));
+                    let node = Node::Text(string.to_string());
+                    if let Some(&mut (ref mut next_down, _, _)) = out_stack.last_mut() {
+                        // only elements and directives are ever pushed on the stack
+                        next_down.append_child(node);
+                    } else {
+                        out_nodes.push(node);
+                    }
                 },
             }
+        } else if mode == ParseMode::Code && code_started {
+            blank_code_lines += 1;
         }
     }
 
     // push remainder on
-    while let Some((node, _)) = out_stack.pop() {
-        if let Some(&mut (ref mut next_down, _)) = out_stack.last_mut() {
-//            println!("!push ele {:?} to next down {:?}", ele.name, next_down.name);
-            if !next_down.append_child(node.clone()) {
-                return Err(ParseError {
-                    line_number: 0,
-                    context: vec![],
-                    character: None,
-                    reason: ErrorReason::IllegalNesting(format!("parent -> {:?} child -> {:?}", next_down, node)),
-                });
+    while let Some((mut node, node_indent, node_line)) = out_stack.pop() {
+        dedent_code(&mut node);
+        trace!(TraceEvent::Close { line_number: lines.len(), indent: node_indent, node: describe(&node), into_root: out_stack.is_empty() });
+        if let Some(&mut (ref mut next_down, _, _)) = out_stack.last_mut() {
+            if next_down.supports_children() {
+                next_down.append_child(node);
+            } else {
+                errors.push(error_at(&lines, node_line, None, illegal_nesting(next_down, &node, recover)));
+                if !recover {
+                    break;
+                }
             }
         } else {
-//            println!("!push ele to root {:?}", ele.name);
             out_nodes.push(node);
         }
     }
 
-    Ok(out_nodes)
-}
\ No newline at end of file
+    RecoveredParse {
+        nodes: out_nodes,
+        errors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // the trees and errors here are the ones the nom grammar this parser replaced gave
+    use super::*;
+
+    fn el(name: &str, attributes: Vec<(&str, &str)>, children: Vec<Node>) -> Node {
+        let mut ele = element(name, attributes);
+        ele.children = children;
+        Node::Element(ele)
+    }
+
+    fn text(text: &str) -> Node {
+        Node::Text(text.to_string())
+    }
+
+    fn raw(text: &str) -> Node {
+        Node::RawText(text.to_string())
+    }
+
+    fn assert_tree(source: &str, expected: Vec<Node>) {
+        let nodes = parse(source).expect("the source to parse");
+        assert_eq!(format!("{:?}", nodes), format!("{:?}", expected));
+    }
+
+    fn assert_error(source: &str, line_number: usize, reason: &str) {
+        let err = parse(source).expect_err("a parse error");
+        assert_eq!((err.line_number, format!("{:?}", err.reason)), (line_number, reason.to_string()));
+    }
+
+    #[test]
+    fn elements_nest_by_indentation() {
+        assert_tree("html\n  head\n    title Hello\n  body\n    p some text\n", vec![
+            el("html", vec![], vec![
+                el("head", vec![], vec![el("title", vec![], vec![text("Hello")])]),
+                el("body", vec![], vec![el("p", vec![], vec![text("some text")])]),
+            ]),
+        ]);
+    }
+
+    #[test]
+    fn shorthand_is_a_div_with_the_id_then_the_class_last() {
+        assert_tree(".intro#top\n  | text\nspan.a.b#c inner\n", vec![
+            el("div", vec![("id", "top"), ("class", "intro")], vec![text("text")]),
+            el("span", vec![("id", "c"), ("class", "a b")], vec![text("inner")]),
+        ]);
+    }
+
+    #[test]
+    fn attributes_are_quoted_or_run_to_whitespace() {
+        assert_tree("a href=/ title=\"a title\" link\nimg src=x.png alt=\"\"\n", vec![
+            el("a", vec![("href", "/"), ("title", "a title")], vec![text("link")]),
+            el("img", vec![("src", "x.png"), ("alt", "")], vec![]),
+        ]);
+    }
+
+    #[test]
+    fn javascript_and_css_blocks_hold_their_lines_as_raw_text() {
+        assert_tree(":javascript\n  var x = 1;\n  if (x) {\n    go();\n  }\np after\n", vec![
+            el("script", vec![("type", "text/javascript")], vec![raw("\n"), raw("var x = 1;"), raw("if (x) {"), raw("go();"), raw("}")]),
+            el("p", vec![], vec![text("after")]),
+        ]);
+        assert_tree(":css\n  body { color: red; }\n", vec![
+            el("style", vec![], vec![raw("\n"), raw("body { color: red; }")]),
+        ]);
+    }
+
+    #[test]
+    fn directives_take_children() {
+        assert_tree("doctype html\n=if page.title\n  h1 #{page.title}\n=yield\n", vec![
+            Node::Doctype("html".to_string()),
+            Node::Directive { command: "if page.title".to_string(), children: vec![el("h1", vec![], vec![text("#{page.title}")])] },
+            Node::Directive { command: "yield".to_string(), children: vec![] },
+        ]);
+    }
+
+    #[test]
+    fn doctype_only_comes_first() {
+        assert_error("html\n  doctype html\n", 1, "MisplacedDocType");
+    }
+
+    #[test]
+    fn an_element_has_one_id() {
+        assert_error("p#a#b\n", 0, "MultipleIds");
+    }
+
+    #[test]
+    fn unknown_lines_fail_to_parse() {
+        // the nom grammar described where it gave up rather than why, only the line is the same
+        let err = parse("div\n  ^bad\n").expect_err("a parse error");
+        assert_eq!(err.line_number, 1);
+        assert_eq!(err.character, Some(2));
+        match err.reason {
+            ErrorReason::Parse(ref message) => assert_eq!(message, "a line cannot start with '^'"),
+            ref other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn illegal_nesting_names_both_nodes() {
+        // only nodes that take children are ever opened, so no source gets here
+        let reason = illegal_nesting(&text("a"), &text("b"), false);
+        assert_eq!(format!("{:?}", reason), r#"IllegalNesting("parent -> Text(\"a\") child -> Text(\"b\")")"#);
+    }
+
+    #[test]
+    fn tabs_and_spaces_are_not_mixed() {
+        assert_error("div\n\tp\n  p\n", 2, "MixedIndentation");
+    }
+
+    #[test]
+    fn dedents_line_up_with_a_parent() {
+        assert_error("div\n    p\n  p\n", 2, "UnmatchedDedent(2)");
+    }
+
+    #[test]
+    fn text_doctypes_and_void_elements_have_no_children() {
+        assert_error("| text\n  p\n", 1, r#"NestedInVoid("text")"#);
+        assert_error("doctype html\n  p\n", 1, r#"NestedInVoid("doctype")"#);
+        assert_error("img src=x\n  p\n", 1, r#"NestedInVoid("img")"#);
+    }
+
+    #[test]
+    fn recovering_skips_bad_lines_and_their_children() {
+        let source = "div\n  p#a#b\n    span child\n  p ok\n  ^bad\n    skipped\n  p end\n";
+        let parsed = parse_recovering(source);
+        assert_eq!(format!("{:?}", parsed.nodes), format!("{:?}", vec![
+            el("div", vec![], vec![el("p", vec![], vec![text("ok")]), el("p", vec![], vec![text("end")])]),
+        ]));
+        let errors : Vec<(usize, bool)> = parsed.errors.iter().map(|e| (e.line_number, matches!(e.reason, ErrorReason::Parse(_)))).collect();
+        assert_eq!(errors, vec![(1, false), (4, true)]);
+        assert_eq!(format!("{:?}", parsed.errors[0].reason), "MultipleIds");
+    }
+
+    #[test]
+    fn without_recovering_the_first_error_stops_parsing() {
+        let parsed = parse_with("div\n  p#a#b\n  ^bad\n", ParseOptions::default());
+        assert_eq!(parsed.errors.len(), 1);
+        assert_eq!(parsed.errors[0].line_number, 1);
+    }
+
+    #[test]
+    fn errors_show_the_lines_leading_up_to_them() {
+        let err = parse("a\nb\nc\nd\ne\nf\ng\n  p#a#b\n").expect_err("a parse error");
+        assert_eq!(err.context, vec!["c", "d", "e", "f", "g", "  p#a#b"]);
+    }
+
+    #[test]
+    fn illegal_nesting_says_when_the_child_is_left_out() {
+        let reason = illegal_nesting(&text("a"), &text("b"), true);
+        assert_eq!(format!("{}", reason), r#"illegal nesting: parent -> Text("a") child -> Text("b"), the child is left out"#);
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use {Filters, Node, TemplateContext, Value};
+use directives::{self, DirectiveError};
+use output::{self, DirectiveHandler, OutputMode, Sink, WriteError};
+use parse::{self, ParseError, ParseOptions};
+
+pub type RenderError = WriteError<DirectiveError>;
+
+/// A template parsed once and rendered any number of times with different data.
+///
+/// Understands the standard directives plus `=include name`, which renders a partial added with
+/// `add_partial`, passing the include's children on to its `=yield`.
+///
+/// Text and attribute values interpolate `#{expression}`, escaped, and `!{expression}`, as it is.
+/// Write `\#{` or `\!{` for the characters themselves.
+#[derive(Debug, Clone)]
+pub struct Template {
+    nodes: Vec<Node>,
+    partials: BTreeMap<String, Vec<Node>>,
+    filters: Arc<Filters>,
+    pub indent_size: usize, // 0 renders everything on one line
+    pub mode: OutputMode,
+}
+
+impl Template {
+    pub fn compile(source:&str) -> Result<Template, ParseError> {
+        Template::compile_with(source, OutputMode::Html)
+    }
+
+    /// Compiles a template for XML or text output, where HTML's void elements may have children.
+    pub fn compile_with(source:&str, mode: OutputMode) -> Result<Template, ParseError> {
+        let mut template = Template::from_nodes(Vec::new());
+        template.mode = mode;
+        template.nodes = template.parse(source)?;
+        Ok(template)
+    }
+
+    /// A template from nodes which have already been parsed, as code generated by `codegen` does.
+    pub fn from_nodes(nodes: Vec<Node>) -> Template {
+        Template {
+            nodes,
+            partials: BTreeMap::new(),
+            filters: Arc::new(Filters::standard()),
+            indent_size: 2,
+            mode: OutputMode::Html,
+        }
+    }
+
+    pub fn add_partial(&mut self, name:&str, source:&str) -> Result<(), ParseError> {
+        let nodes = self.parse(source)?;
+        self.add_partial_nodes(name, nodes);
+        Ok(())
+    }
+
+    pub fn add_partial_nodes(&mut self, name:&str, nodes: Vec<Node>) {
+        self.partials.insert(name.to_string(), nodes);
+    }
+
+    /// The partials an `=include` in the template or one of its partials names which haven't been
+    /// added yet, so they can be loaded as they turn out to be needed.
+    pub fn missing_partials(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        included_partials(&self.nodes, &mut names);
+        for nodes in self.partials.values() {
+            included_partials(nodes, &mut names);
+        }
+        names.retain(|name| !self.partials.contains_key(name));
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Makes a filter available to expressions, alongside the standard ones.
+    pub fn register_filter<F>(&mut self, name:&str, filter: F) where F: Fn(&Value, &[Value]) -> Result<Value, String> + Send + Sync + 'static {
+        Arc::make_mut(&mut self.filters).register(name, filter);
+    }
+
+    fn parse(&self, source:&str) -> Result<Vec<Node>, ParseError> {
+        let options = ParseOptions { void_elements: self.mode == OutputMode::Html, ..ParseOptions::default() };
+        let mut parsed = parse::parse_with(source, options);
+        if parsed.errors.is_empty() {
+            Ok(parsed.nodes)
+        } else {
+            Err(parsed.errors.remove(0))
+        }
+    }
+
+    pub fn render(&self, data: Value) -> Result<String, RenderError> {
+        let context = TemplateContext::with_data(data).with_filters(self.filters.clone());
+        let mut handler = TemplateDirectives { partials: &self.partials };
+        output::render_to_string(&self.nodes, &context, self.mode, 0, self.indent_size, &mut handler)
+    }
+}
+
+struct TemplateDirectives<'a> {
+    partials: &'a BTreeMap<String, Vec<Node>>,
+}
+
+impl<'a> DirectiveHandler for TemplateDirectives<'a> {
+    type DirectiveError = DirectiveError;
+
+    fn handle(&mut self, context:&mut TemplateContext, command: &str, children: &[Node], base_indent: usize, indent_size: usize, sink: &mut Sink) -> Result<(), DirectiveError> {
+        let result = if let Some(name) = command.strip_prefix("include ") {
+            let name = name.trim();
+            let partials = self.partials;
+            match partials.get(name) {
+                Some(nodes) => output::render(nodes, &mut context.yielding(children.to_vec()), sink, base_indent, indent_size, self),
+                None => return Err(error(command, format!("no partial named {}", name))),
+            }
+        } else {
+            match directives::standard(self, context, command, children, base_indent, indent_size, sink) {
+                Some(result) => result,
+                None => return Err(error(command, "unrecognized".to_string())),
+            }
+        };
+
+        result.map_err(|err| match err {
+            WriteError::DirectiveError(err) => err,
+            WriteError::IO(err) => error(command, format!("{}", err)),
+            WriteError::Expression(err) => error(command, format!("{}", err)),
+        })
+    }
+}
+
+/// Adds the name of every partial that nodes `=include`, at any depth.
+pub(crate) fn included_partials(nodes:&[Node], names:&mut Vec<String>) {
+    for node in nodes {
+        match node {
+            Node::Directive { command, children } => {
+                if let Some(name) = command.strip_prefix("include ") {
+                    names.push(name.trim().to_string());
+                }
+                included_partials(children, names);
+            },
+            Node::Element(element) => included_partials(&element.children, names),
+            _ => {},
+        }
+    }
+}
+
+fn error(command:&str, reason: String) -> DirectiveError {
+    DirectiveError {
+        directive: command.to_string(),
+        reason,
+    }
+}